@@ -1,41 +1,176 @@
-use std::{borrow::Cow, collections::HashMap, fmt::Debug, hash::{Hash, Hasher}, io::Read, num::ParseIntError, sync::{atomic::{AtomicBool, AtomicU32}, Arc}};
+use std::{borrow::Cow, collections::HashMap, fmt::Debug, hash::{Hash, Hasher}, io::Read, num::ParseIntError, sync::{atomic::{AtomicBool, AtomicU32, AtomicU64}, Arc}};
 
 use egui::Color32;
 use image::DynamicImage;
 use serde::{Deserialize, Serialize};
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, sync::{Mutex, RwLock}};
+use tokio::sync::{Mutex, RwLock};
 
-use crate::{load_misskey::{RawFile, RawInstance, RawNote, RawUser}, ConfigFile, StateFile};
+use crate::{disk_cache::{self, CacheLookup}, image_decode::{self, ImageSize}, load_misskey::{RawFile, RawInstance, RawNote, RawUser}, mfm::{self, MfmNode}, ConfigFile, StateFile};
 
 const DUMMY_PNG:&'static str="local://dummy.png";
 pub const DEFAULT_ANIMATION:bool=true;
+//GPUに載せたテクスチャの総量と描画したフレーム番号
+static GPU_BYTES:AtomicU64=AtomicU64::new(0);
+static GPU_TEXTURES:AtomicU64=AtomicU64::new(0);
+//画像を退避するたびに増やして、ノート側で確認が必要か判断する
+static EVICT_GEN:AtomicU64=AtomicU64::new(0);
+static FRAME:AtomicU64=AtomicU64::new(0);
+pub const DEFAULT_TEXTURE_BUDGET_MB:u64=256;
+//この間描画されていなければ退避してよい
+const TEXTURE_KEEP_FRAMES:u64=120;
+pub fn next_frame(){
+	FRAME.fetch_add(1,std::sync::atomic::Ordering::Relaxed);
+}
+//(テクスチャ数,バイト数)
+pub fn texture_usage()->(u64,u64){
+	(GPU_TEXTURES.load(std::sync::atomic::Ordering::Relaxed),GPU_BYTES.load(std::sync::atomic::Ordering::Relaxed))
+}
+pub fn over_texture_budget()->bool{
+	GPU_BYTES.load(std::sync::atomic::Ordering::Relaxed)>DEFAULT_TEXTURE_BUDGET_MB*1024*1024
+}
 pub(crate) fn cache_dir()->String{
 	std::env::var("YAC_CACHE_PATH").unwrap_or_else(|_|"cache".to_owned())
 }
+pub fn time_ago_label(created_at:&chrono::DateTime<chrono::Utc>)->String{
+	let secs_ago=chrono::Utc::now().timestamp()-created_at.timestamp();
+	if secs_ago>12*30*24*60*60{
+		format!("{}年前",secs_ago/(12*30*24*60*60))
+	}else if secs_ago>30*24*60*60{
+		format!("{}ヶ月前",secs_ago/(30*24*60*60))
+	}else if secs_ago>7*24*60*60{
+		format!("{}週間前",secs_ago/(7*24*60*60))
+	}else if secs_ago>24*60*60{
+		format!("{}日前",secs_ago/(24*60*60))
+	}else if secs_ago>60*60{
+		format!("{}時間前",secs_ago/(60*60))
+	}else if secs_ago>60{
+		format!("{}分前",secs_ago/60)
+	}else{
+		format!("{}秒前",secs_ago)
+	}
+}
 #[derive(Debug)]
 pub struct Note{
 	pub id:String,
 	pub user:Arc<UserProfile>,
 	pub quote:Option<Arc<Note>>,
+	pub reply:Option<Arc<Note>>,
+	pub reply_id:Option<String>,
 	pub text:MFMString,
 	pub visibility:Visibility,
 	pub reactions: Reactions,
 	pub files:Vec<NoteFile>,
 	pub cw:Option<MFMString>,
+	pub poll:Option<Poll>,
 	pub created_at: chrono::prelude::DateTime<chrono::prelude::Utc>,
 	pub height:AtomicU32,
+	//最後に退避された画像を確認した時のEVICT_GEN
+	pub evict_gen:AtomicU64,
+}
+//アンケート
+#[derive(Clone,Debug,PartialEq)]
+pub struct Poll{
+	pub choices:Vec<PollChoice>,
+	pub multiple:bool,
+	pub expires_at:Option<chrono::DateTime<chrono::Utc>>,
+}
+#[derive(Clone,Debug,PartialEq)]
+pub struct PollChoice{
+	pub text:String,
+	pub votes:u64,
+	pub is_voted:bool,
+}
+impl Poll{
+	pub fn total_votes(&self)->u64{
+		self.choices.iter().map(|c|c.votes).sum()
+	}
+	pub fn is_expired(&self)->bool{
+		self.expires_at.map(|t|t<chrono::Utc::now()).unwrap_or(false)
+	}
+	//pollVotedで届いた1票を足す
+	pub fn voted(&self,choice:usize,mine:bool)->Self{
+		let mut poll=self.clone();
+		if let Some(c)=poll.choices.get_mut(choice){
+			c.votes+=1;
+			if mine{
+				c.is_voted=true;
+			}
+		}
+		poll
+	}
 }
 impl PartialEq for Note{
 	fn eq(&self, other: &Self) -> bool {
 		self.id==other.id&&
 		self.reactions.hash==other.reactions.hash&&
+		self.poll==other.poll&&
 		self.quote.is_some()==other.quote.is_some()&&
 		self.created_at==other.created_at
 	}
 }
 impl Note{
+	pub fn evict_images(&self){
+		for img in self.images(){
+			img.evict();
+		}
+	}
+	//前回の確認以降に退避が起きていて、このノートの画像が含まれていればtrue
+	pub fn take_evicted(&self)->bool{
+		let gen=EVICT_GEN.load(std::sync::atomic::Ordering::Relaxed);
+		if self.evict_gen.swap(gen,std::sync::atomic::Ordering::Relaxed)==gen{
+			return false;
+		}
+		self.images().iter().any(|img|img.is_evicted())
+	}
+	//退避と再読込の対象になる画像
+	fn images(&self)->Vec<&UrlImage>{
+		let mut images:Vec<&UrlImage>=vec![&self.user.icon];
+		if let Some(instance)=self.user.instance.as_ref(){
+			images.push(&instance.icon);
+		}
+		images.extend(self.user.display_name.emojis().map(|e|e.as_ref()));
+		images.extend(self.text.emojis().map(|e|e.as_ref()));
+		images.extend(self.cw.iter().flat_map(|cw|cw.emojis()).map(|e|e.as_ref()));
+		images.extend(self.reactions.emojis().map(|e|e.as_ref()));
+		for f in &self.files{
+			images.extend(f.img.iter().chain(f.original_img.iter()).map(|e|e.as_ref()));
+		}
+		for n in self.quote.iter().chain(self.reply.iter()){
+			images.extend(n.images());
+		}
+		images
+	}
+	//リアクションだけ差し替えた複製
+	pub fn with_reactions(&self,reactions:Reactions)->Self{
+		Self{
+			id:self.id.clone(),
+			user:self.user.clone(),
+			quote:self.quote.clone(),
+			reply:self.reply.clone(),
+			reply_id:self.reply_id.clone(),
+			text:self.text.clone(),
+			visibility:self.visibility,
+			reactions,
+			files:self.files.clone(),
+			cw:self.cw.clone(),
+			poll:self.poll.clone(),
+			created_at:self.created_at,
+			height:AtomicU32::new(self.height.load(std::sync::atomic::Ordering::Relaxed)),
+			evict_gen:AtomicU64::new(0),
+		}
+	}
+	pub fn with_poll(&self,poll:Poll)->Self{
+		let mut note=self.with_reactions(self.reactions.clone());
+		note.poll=Some(poll);
+		note
+	}
+	pub fn with_quote(&self,quote:Arc<Note>)->Self{
+		let mut note=self.with_reactions(self.reactions.clone());
+		note.quote=Some(quote);
+		note
+	}
 	pub fn is_simple_renote(&self)->bool{
-		self.text.raw.is_empty()&&self.files.len()==0
+		self.text.raw.is_empty()&&self.files.len()==0&&self.poll.is_none()
 	}
 	pub fn can_renote(&self)->bool{
 		if self.is_simple_renote(){
@@ -53,22 +188,7 @@ impl Note{
 		}
 	}
 	pub fn created_at_label(&self)->String{
-		let secs_ago=chrono::Utc::now().timestamp()-self.created_at.timestamp();
-		if secs_ago>12*30*24*60*60{
-			format!("{}年前",secs_ago/(12*30*24*60*60))
-		}else if secs_ago>30*24*60*60{
-			format!("{}ヶ月前",secs_ago/(30*24*60*60))
-		}else if secs_ago>7*24*60*60{
-			format!("{}週間前",secs_ago/(7*24*60*60))
-		}else if secs_ago>24*60*60{
-			format!("{}日前",secs_ago/(24*60*60))
-		}else if secs_ago>60*60{
-			format!("{}時間前",secs_ago/(60*60))
-		}else if secs_ago>60{
-			format!("{}分前",secs_ago/60)
-		}else{
-			format!("{}秒前",secs_ago)
-		}
+		time_ago_label(&self.created_at)
 	}
 	pub async fn system_message(text:impl Into<String>,name:impl Into<String>)->Self{
 		let emoji_cache=EmojiCache::new("","localhost",Arc::new(HashMap::new()));
@@ -79,23 +199,78 @@ impl Note{
 			user: Arc::new(UserProfile{
 				id: "system".to_owned(),
 				username: "system".to_owned(),
-				display_name: MFMString::new(name.into(),None,instance.clone(),&emoji_cache).await,
+				display_name: MFMString::new_simple(name.into(),None,instance.clone(),&emoji_cache).await,
 				instance: None,
 				icon: DUMMY_PNG.to_owned().into(),
 			}),
 			quote: None,
+			reply: None,
+			reply_id: None,
 			text: MFMString::new(text.into(),None,instance,&emoji_cache).await,
 			visibility: Visibility::Public,
 			reactions: Reactions{
 				emojis: vec![],
+				my_reaction: None,
 				hash:0,
 			},
 			files: vec![],
 			cw:None,
+			poll:None,
 			height:AtomicU32::new(0),
+			evict_gen:AtomicU64::new(0),
+		}
+	}
+}
+#[derive(Debug)]
+pub struct Notification{
+	pub id:String,
+	pub kind:NotificationKind,
+	pub user:Option<Arc<UserProfile>>,
+	pub note:Option<Arc<Note>>,
+	pub reaction:Option<Emoji>,
+	pub created_at: chrono::prelude::DateTime<chrono::prelude::Utc>,
+}
+#[derive(PartialEq,Eq,Clone,Debug)]
+pub enum NotificationKind{
+	Reaction,
+	Renote,
+	Quote,
+	Reply,
+	Mention,
+	Follow,
+	ReceiveFollowRequest,
+	FollowRequestAccepted,
+	Other(String),
+}
+impl From<&str> for NotificationKind{
+	fn from(value: &str) -> Self {
+		match value{
+			"reaction"=>Self::Reaction,
+			"renote"=>Self::Renote,
+			"quote"=>Self::Quote,
+			"reply"=>Self::Reply,
+			"mention"=>Self::Mention,
+			"follow"=>Self::Follow,
+			"receiveFollowRequest"=>Self::ReceiveFollowRequest,
+			"followRequestAccepted"=>Self::FollowRequestAccepted,
+			other=>Self::Other(other.to_owned()),
 		}
 	}
 }
+#[derive(Debug)]
+pub struct NoteThread{
+	pub id:String,
+	//根から順
+	pub ancestors:Vec<Arc<Note>>,
+	pub note:Option<Arc<Note>>,
+	//深さと返信
+	pub children:Vec<(usize,Arc<Note>)>,
+}
+impl Notification{
+	pub fn created_at_label(&self)->String{
+		time_ago_label(&self.created_at)
+	}
+}
 #[derive(Clone,Debug)]
 pub struct NoteFile{
 	pub(crate) img:Option<Arc<UrlImage>>,
@@ -219,16 +394,47 @@ impl UserProfile{
 		let icon_url=user.avatar_url.as_ref().map(|u|u.to_string()).unwrap_or_else(||
 			format!("{}/avatar/@{}{}",emoji_cache.local_instance.as_str(),user.username,user.host.as_ref().map(|s|format!("@{}",s)).unwrap_or_default())
 		);
-		let display_name=MFMString::new(user.name.as_ref().unwrap_or_else(||&user.username).to_owned(),user.emojis.as_ref(),f.as_ref(),&emoji_cache).await;
+		let display_name=MFMString::new_simple(user.name.as_ref().unwrap_or_else(||&user.username).to_owned(),user.emojis.as_ref(),f.as_ref(),&emoji_cache).await;
 		Self{
 			instance:f,
 			id: user.id.to_string(),
 			display_name,
 			username: user.username.clone(),
-			icon: icon_url.into(),
+			icon: (icon_url,ImageSize::Icon).into(),
 		}
 	}
 }
+//notes/reactionsで取得したリアクションしたユーザー
+#[derive(Debug)]
+pub struct ReactionUsers{
+	pub note_id:String,
+	pub reaction:String,
+	//失敗したらその理由
+	pub users:Result<Vec<(String,Arc<UserProfile>)>,String>,
+	pub is_next:bool,
+}
+//users/showで取得するプロフィール
+#[derive(Debug)]
+pub struct UserDetail{
+	pub user:Arc<UserProfile>,
+	pub banner:Option<Arc<UrlImage>>,
+	pub description:Option<MFMString>,
+	pub fields:Vec<(MFMString,MFMString)>,
+	pub followers_count:u64,
+	pub following_count:u64,
+	pub notes_count:u64,
+	pub created_at:chrono::DateTime<chrono::Utc>,
+	pub pinned_notes:Vec<Arc<Note>>,
+}
+impl UserDetail{
+	pub fn emojis(&self)->impl Iterator<Item=&Arc<UrlImage>>{
+		let fields=self.fields.iter().flat_map(|(name,value)|name.emojis().chain(value.emojis()));
+		self.description.iter().flat_map(|d|d.emojis()).chain(fields)
+	}
+	pub fn created_at_label(&self)->String{
+		self.created_at.with_timezone(&chrono::Local).format("%Y/%m/%d").to_string()
+	}
+}
 #[derive(Clone,Debug)]
 pub struct EmojiCache{
 	media_proxy:String,
@@ -238,6 +444,7 @@ pub struct EmojiCache{
 }
 pub enum DelayAssets{
 	Note(Arc<Note>),
+	User(Arc<UserProfile>),
 	Emoji(EmojiCache,LocalEmojis),
 	Image(Arc<UrlImage>),
 	UpdateState(Arc<StateFile>),
@@ -348,7 +555,7 @@ impl EmojiCache{
 		println!("load emoji {:?}",unique_emoji_id);
 		let remote_url=urlencoding::encode(url);
 		let local_url=format!("{}/emoji.webp?url={}&emoji=1",self.media_proxy,remote_url);
-		let img:Arc<UrlImage>=Arc::new(local_url.into());
+		let img:Arc<UrlImage>=Arc::new((local_url,ImageSize::Emoji).into());
 		self.map.write().await.insert(unique_emoji_id.id().to_string(),img.clone());
 		Emoji{
 			id:unique_emoji_id,
@@ -372,14 +579,63 @@ impl EmojiCache{
 		remove_targets.len()
 	}
 }
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct Reactions{
-	pub emojis:Vec<(Emoji,u64)>,
+	//絵文字,数,リアクション文字列
+	pub emojis:Vec<(Emoji,u64,String)>,
+	pub my_reaction:Option<String>,
 	pub hash:u64,
 }
 impl Reactions{
 	pub fn emojis(&self)->impl Iterator<Item=&Arc<UrlImage>>{
-		self.emojis.iter().map(|(emoji,_)|&emoji.img)
+		self.emojis.iter().map(|(emoji,_,_)|&emoji.img)
+	}
+	pub fn is_mine(&self,reaction:&str)->bool{
+		self.my_reaction.as_ref().map(|mine|mine==reaction).unwrap_or(false)
+	}
+	//ストリーミングで届いたリアクションを反映する
+	pub async fn reacted(&self,reaction:&str,reaction_emojis:&HashMap<String,String>,emoji_cache:&EmojiCache,mine:bool)->Self{
+		let mut emojis=self.emojis.clone();
+		if let Some((_,count,_))=emojis.iter_mut().find(|(_,_,r)|r==reaction){
+			*count+=1;
+		}else if let Some(emoji)=Emoji::from_reaction(reaction,reaction_emojis,emoji_cache).await{
+			emojis.push((emoji,1,reaction.to_owned()));
+		}
+		let my_reaction=if mine{
+			Some(reaction.to_owned())
+		}else{
+			self.my_reaction.clone()
+		};
+		Self::update(emojis,my_reaction)
+	}
+	pub fn unreacted(&self,reaction:&str,mine:bool)->Self{
+		let mut emojis=self.emojis.clone();
+		if let Some((_,count,_))=emojis.iter_mut().find(|(_,_,r)|r==reaction){
+			*count=count.saturating_sub(1);
+		}
+		emojis.retain(|(_,count,_)|*count>0);
+		let my_reaction=if mine{
+			None
+		}else{
+			self.my_reaction.clone()
+		};
+		Self::update(emojis,my_reaction)
+	}
+	fn update(mut emojis:Vec<(Emoji,u64,String)>,my_reaction:Option<String>)->Self{
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		let mut hash=0;
+		for (_,count,reaction) in &emojis{
+			reaction.hash(&mut hasher);
+			hash+=*count;
+		}
+		my_reaction.hash(&mut hasher);
+		hash+=hasher.finish();
+		emojis.sort_by(|(_,a,_),(_,b,_)|b.cmp(a));
+		Self{
+			emojis,
+			my_reaction,
+			hash,
+		}
 	}
 }
 impl Reactions{
@@ -391,49 +647,21 @@ impl Reactions{
 		let mut emojis=vec![];
 		let mut hash=0;
 		for (reaction,count) in &note.reactions{
-			if reaction.ends_with("@.:"){//isLocalEmoji
-				let id=reaction[1..reaction.len()-3].to_string();
-				let url=emoji_cache.local_emojis.get(&id);
-				if let Some(url)=url{
-					reaction.hash(&mut hasher);
-					let id=EmojiId::Local(id);
-					let emoji=emoji_cache.load(id,url.as_str()).await;
-					hash+=*count;
-					emojis.push((emoji,*count));
-				}else{
-					println!("ローカル絵文字が見つからない?{}",id);
-				}
-			}else if reaction.contains("@"){
-				//リモート絵文字
-				let id=reaction[1..reaction.len()-1].to_string();
-				let url=note.reaction_emojis.get(&id);
-				if let Some(url)=url{
-					reaction.hash(&mut hasher);
-					let id=EmojiId::Remote(id);
-					let emoji=emoji_cache.load(id,url.as_str()).await;
-					hash+=*count;
-					emojis.push((emoji,*count));
-				}else{
-					println!("リモート絵文字が見つからない?{}",id);
-				}
-			}else{
-				//おそらくUnicode絵文字
-				//let id=hex::encode(reaction.0.as_bytes());
-				if let Some((c,url))=unicode_to_emoji(&reaction,&emoji_cache.local_instance){
-					char::from_u32(c.0).unwrap().to_string().hash(&mut hasher);
-					let id=EmojiId::Unicode(c);
-					let emoji=emoji_cache.load(id,url.as_str()).await;
-					hash+=*count;
-					emojis.push((emoji,*count));
-				}else{
-					println!("Unicode絵文字が見つからない?{}",reaction);
+			if let Some(emoji)=Emoji::from_reaction(reaction,&note.reaction_emojis,emoji_cache).await{
+				match &emoji.id{
+					EmojiId::Unicode(c)=>char::from_u32(c.0).unwrap().to_string().hash(&mut hasher),
+					_=>reaction.hash(&mut hasher),
 				}
+				hash+=*count;
+				emojis.push((emoji,*count,reaction.clone()));
 			}
 		}
+		note.my_reaction.hash(&mut hasher);
 		hash+=hasher.finish();
-		emojis.sort_by(|(_,a),(_,b)|b.cmp(a));
+		emojis.sort_by(|(_,a,_),(_,b,_)|b.cmp(a));
 		Self {
 			emojis,
+			my_reaction:note.my_reaction.clone(),
 			hash,
 		}
 	}
@@ -454,17 +682,38 @@ fn unicode_to_emoji(unicode:&str,local_instance:&str)->Option<(UnicodeEmoji,Stri
 	let url=format!("{}/twemoji/{}.svg",local_instance,&id);
 	Some((UnicodeEmoji(c),url))
 }
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct MFMString{
 	pub raw:String,
 	render:Vec<MFMElement>,
 }
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 enum MFMElement{
 	Text(String),
 	Emoji(Emoji),
-	Scale(f32,Box<MFMElement>),
+	Scale(f32,Vec<MFMElement>),
 	Url(String,Arc<Mutex<Option<Summaly>>>),
+	Link(String,Vec<MFMElement>,Option<Arc<Mutex<Option<Summaly>>>>),
+	Mention{
+		acct:String,
+		username:String,
+		host:Option<String>,
+	},
+	Hashtag(String),
+	InlineCode(String),
+	CodeBlock(String),
+	Bold(Vec<MFMElement>),
+	Italic(Vec<MFMElement>),
+	Strike(Vec<MFMElement>),
+	Small(Vec<MFMElement>),
+	Center(Vec<MFMElement>),
+	Quote(Vec<MFMElement>),
+	Search(String),
+	Fg(Color32,Vec<MFMElement>),
+	Bg(Color32,Vec<MFMElement>),
+	Flip(bool,bool,Vec<MFMElement>),
+	Blur(Vec<MFMElement>),
+	Ruby(Vec<MFMElement>,String),
 }
 #[derive(Debug)]
 pub struct Summaly{
@@ -508,63 +757,372 @@ impl From<SummalyRaw> for Summaly{
 			title: raw.title,
 			sitename: raw.sitename,
 			description: raw.description,
-			icon: raw.icon.map(|v|Arc::new(v.into())),
+			icon: raw.icon.map(|v|Arc::new((v,ImageSize::Icon).into())),
 			thumbnail: raw.thumbnail.map(|v|Arc::new(v.into()))
 		}
 	}
 }
+#[derive(Clone)]
 struct MFMRenderContext{
 	scale:f32,
+	strong:bool,
+	italics:bool,
+	strike:bool,
+	small:bool,
+	fg:Option<Color32>,
+	bg:Option<Color32>,
+	flip:(bool,bool),
+	blur:bool,
+	link:Option<String>,
 }
 impl MFMRenderContext{
-	fn new()->Self{
+	fn new(strong:bool)->Self{
 		Self{
 			scale:1f32,
+			strong,
+			italics:false,
+			strike:false,
+			small:false,
+			fg:None,
+			bg:None,
+			flip:(false,false),
+			blur:false,
+			link:None,
 		}
 	}
+	fn text(&self,s:impl Into<String>)->egui::RichText{
+		let size=if self.small{
+			10f32
+		}else{
+			12f32
+		};
+		let text=egui::RichText::new(s).size(size*self.scale);
+		let text=if self.strong{
+			text.strong()
+		}else{
+			text
+		};
+		let text=if self.italics{
+			text.italics()
+		}else{
+			text
+		};
+		let text=if self.strike{
+			text.strikethrough()
+		}else{
+			text
+		};
+		let text=if self.small{
+			text.color(Color32::from_gray(140))
+		}else{
+			text
+		};
+		let text=match self.fg{
+			Some(c)=>text.color(c),
+			None=>text,
+		};
+		let text=match self.bg{
+			Some(c)=>text.background_color(c),
+			None=>text,
+		};
+		if self.blur{
+			//ぼかしの代わりに塗りつぶす
+			text.color(Color32::from_gray(128)).background_color(Color32::from_gray(128))
+		}else{
+			text
+		}
+	}
+	fn child(&self,f:impl FnOnce(&mut Self))->Self{
+		let mut ctx=self.clone();
+		f(&mut ctx);
+		ctx
+	}
+}
+//メンションやハッシュタグのクリックはフレームの終わりにUI側で処理する
+#[derive(Clone,Debug)]
+pub enum MFMClick{
+	Mention{
+		username:String,
+		host:Option<String>,
+	},
+	Hashtag(String),
+	//検索ブロックはインスタンスの検索で開く
+	Search(String),
+}
+impl MFMClick{
+	fn id()->egui::Id{
+		egui::Id::new("mfm_click")
+	}
+	fn emit(self,ctx:&egui::Context){
+		ctx.data_mut(|d|d.insert_temp(Self::id(),self));
+		ctx.request_repaint();
+	}
+	pub fn take(ctx:&egui::Context)->Option<Self>{
+		ctx.data_mut(|d|{
+			let click=d.get_temp(Self::id());
+			d.remove::<Self>(Self::id());
+			click
+		})
+	}
+}
+const MENTION_COLOR:Color32=Color32::from_rgb(80,160,220);
+fn render_elements(ui:&mut egui::Ui,elements:&[MFMElement],dummy:&UrlImage,ctx:&MFMRenderContext,animate_frame:u64){
+	if !elements.iter().any(|e|e.is_block()){
+		ui.horizontal_wrapped(|ui|{
+			ui.spacing_mut().item_spacing=[0f32,0f32].into();
+			for e in elements{
+				e.render(ui,dummy,ctx,animate_frame);
+			}
+		});
+		return;
+	}
+	//ブロック要素で行を区切る
+	ui.vertical(|ui|{
+		ui.spacing_mut().item_spacing=[0f32,0f32].into();
+		let mut start=0;
+		for (idx,e) in elements.iter().enumerate(){
+			if e.is_block(){
+				if start<idx{
+					render_elements(ui,&elements[start..idx],dummy,ctx,animate_frame);
+				}
+				e.render(ui,dummy,ctx,animate_frame);
+				start=idx+1;
+			}
+		}
+		if start<elements.len(){
+			render_elements(ui,&elements[start..],dummy,ctx,animate_frame);
+		}
+	});
 }
 impl MFMElement{
-	pub fn render(&self,ui:&mut egui::Ui,strong:bool,dummy:&UrlImage,ctx:&mut MFMRenderContext,animate_frame:u64){
+	fn is_block(&self)->bool{
+		match self{
+			MFMElement::CodeBlock(_)|
+			MFMElement::Center(_)|
+			MFMElement::Quote(_)|
+			MFMElement::Search(_)=>true,
+			_=>false,
+		}
+	}
+	fn walk<'a>(&'a self,f:&mut impl FnMut(&'a MFMElement)){
+		f(self);
+		match self{
+			MFMElement::Scale(_,c)|
+			MFMElement::Link(_,c,_)|
+			MFMElement::Bold(c)|
+			MFMElement::Italic(c)|
+			MFMElement::Strike(c)|
+			MFMElement::Small(c)|
+			MFMElement::Center(c)|
+			MFMElement::Quote(c)|
+			MFMElement::Fg(_,c)|
+			MFMElement::Bg(_,c)|
+			MFMElement::Flip(_,_,c)|
+			MFMElement::Blur(c)|
+			MFMElement::Ruby(c,_)=>{
+				for e in c{
+					e.walk(f);
+				}
+			},
+			_=>{}
+		}
+	}
+	fn render_text(ui:&mut egui::Ui,s:&str,ctx:&MFMRenderContext){
+		use egui::Widget;
+		let (h,v)=ctx.flip;
+		//文字を描いた形の頂点を反転して鏡像にする
+		if h||v{
+			let galley=egui::WidgetText::from(ctx.text(s)).into_galley(ui,Some(false),f32::INFINITY,egui::TextStyle::Body);
+			let (rect,_)=ui.allocate_exact_size(galley.size(),egui::Sense::hover());
+			let shape=egui::epaint::TextShape::new(rect.left_top(),galley,ui.visuals().text_color());
+			let font_tex_size=ui.fonts(|f|f.font_image_size());
+			let mut tessellator=egui::epaint::Tessellator::new(ui.ctx().pixels_per_point(),Default::default(),font_tex_size,vec![]);
+			let mut mesh=egui::epaint::Mesh::default();
+			tessellator.tessellate_shape(shape.into(),&mut mesh);
+			let center=rect.center();
+			for vertex in mesh.vertices.iter_mut(){
+				if h{
+					vertex.pos.x=2f32*center.x-vertex.pos.x;
+				}
+				if v{
+					vertex.pos.y=2f32*center.y-vertex.pos.y;
+				}
+			}
+			ui.painter().add(mesh);
+			return;
+		}
+		if let Some(url)=ctx.link.as_ref(){
+			if egui::Link::new(ctx.text(s)).ui(ui).on_hover_text(url).clicked(){
+				ui.ctx().open_url(egui::OpenUrl::new_tab(url));
+			}
+			return;
+		}
+		let res=egui::Label::new(ctx.text(s)).ui(ui);
+		if ctx.blur{
+			res.on_hover_text(s);
+		}
+	}
+	fn render(&self,ui:&mut egui::Ui,dummy:&UrlImage,ctx:&MFMRenderContext,animate_frame:u64){
 		use egui::Widget;
 		match self{
 			MFMElement::Text(s)=>{
-				let text=egui::RichText::from(s);
-				let text=if strong{
-					text.strong()
-				}else{
-					text
-				};
-				let text=text.size(12f32*ctx.scale);
-				egui::Label::new(text).ui(ui);
+				Self::render_text(ui,s,ctx);
 			},
 			MFMElement::Emoji(emoji)=>{
 				let img=emoji.img.get(animate_frame).unwrap_or_else(||dummy.get(animate_frame).unwrap());
 				let img=img.max_size([f32::MAX,20f32*ctx.scale].into());
+				let (h,v)=ctx.flip;
+				let img=if h||v{
+					let (x0,x1)=if h{(1f32,0f32)}else{(0f32,1f32)};
+					let (y0,y1)=if v{(1f32,0f32)}else{(0f32,1f32)};
+					img.uv(egui::Rect::from_min_max(egui::pos2(x0,y0),egui::pos2(x1,y1)))
+				}else{
+					img
+				};
+				let img=if ctx.blur{
+					img.tint(Color32::from_gray(40))
+				}else{
+					img
+				};
 				img.ui(ui).on_hover_text(emoji.id.id().as_str());
 			},
-			MFMElement::Scale(s,e)=>{
-				ctx.scale*=s;
-				e.render(ui,strong,dummy,ctx,animate_frame);
+			MFMElement::Scale(s,c)=>{
+				let ctx=ctx.child(|ctx|ctx.scale*=s);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
 			},
 			MFMElement::Url(url,summaly) => {
 				let lock=summaly.blocking_lock();
 				let hint_url=lock.as_ref().map(|v|&v.url).unwrap_or(url);
-				let s=format!("{}",url);
-				let text=egui::RichText::from(s);
-				let text=if strong{
-					text.strong()
-				}else{
-					text
-				};
-				let text=text.size(12f32*ctx.scale);
+				let text=ctx.text(url.as_str());
 				if egui::Link::new(text).ui(ui).on_hover_text(hint_url).clicked(){
 					ui.ctx().open_url(egui::OpenUrl::new_tab(url));
 				}
 			},
+			MFMElement::Link(url,label,_) => {
+				let ctx=ctx.child(|ctx|ctx.link=Some(url.clone()));
+				for e in label{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Mention{acct,username,host} => {
+				let ctx=ctx.child(|ctx|ctx.fg=Some(MENTION_COLOR));
+				if egui::Link::new(ctx.text(acct.as_str())).ui(ui).clicked(){
+					MFMClick::Mention{
+						username:username.clone(),
+						host:host.clone(),
+					}.emit(ui.ctx());
+				}
+			},
+			MFMElement::Hashtag(tag) => {
+				let ctx=ctx.child(|ctx|ctx.fg=Some(MENTION_COLOR));
+				if egui::Link::new(ctx.text(format!("#{}",tag))).ui(ui).clicked(){
+					MFMClick::Hashtag(tag.clone()).emit(ui.ctx());
+				}
+			},
+			MFMElement::InlineCode(code) => {
+				let text=ctx.text(code.as_str()).monospace().background_color(Color32::from_gray(40)).color(Color32::from_gray(220));
+				egui::Label::new(text).ui(ui);
+			},
+			MFMElement::CodeBlock(code) => {
+				egui::Frame::none().fill(Color32::from_gray(40)).inner_margin(egui::Margin::same(4f32)).show(ui,|ui|{
+					ui.set_width(ui.available_width());
+					let text=ctx.text(code.as_str()).monospace().color(Color32::from_gray(220));
+					egui::Label::new(text).ui(ui);
+				});
+			},
+			MFMElement::Bold(c) => {
+				let ctx=ctx.child(|ctx|ctx.strong=true);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Italic(c) => {
+				let ctx=ctx.child(|ctx|ctx.italics=true);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Strike(c) => {
+				let ctx=ctx.child(|ctx|ctx.strike=true);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Small(c) => {
+				let ctx=ctx.child(|ctx|ctx.small=true);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Center(c) => {
+				ui.vertical_centered(|ui|{
+					render_elements(ui,c,dummy,ctx,animate_frame);
+				});
+			},
+			MFMElement::Quote(c) => {
+				let margin=egui::Margin{
+					left:10f32,
+					..Default::default()
+				};
+				let res=egui::Frame::none().inner_margin(margin).show(ui,|ui|{
+					let ctx=ctx.child(|ctx|ctx.fg=Some(Color32::from_gray(140)));
+					render_elements(ui,c,dummy,&ctx,animate_frame);
+				});
+				let rect=res.response.rect;
+				ui.painter().vline(rect.left()+3f32,rect.y_range(),egui::Stroke::new(3f32,Color32::from_gray(100)));
+			},
+			MFMElement::Search(query) => {
+				ui.horizontal(|ui|{
+					Self::render_text(ui,query,ctx);
+					ui.add_space(5f32);
+					if ui.button("検索").clicked(){
+						MFMClick::Search(query.clone()).emit(ui.ctx());
+					}
+				});
+			},
+			MFMElement::Fg(color,c) => {
+				let ctx=ctx.child(|ctx|ctx.fg=Some(*color));
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Bg(color,c) => {
+				let ctx=ctx.child(|ctx|ctx.bg=Some(*color));
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Flip(h,v,c) => {
+				let ctx=ctx.child(|ctx|ctx.flip=(ctx.flip.0^h,ctx.flip.1^v));
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Blur(c) => {
+				let ctx=ctx.child(|ctx|ctx.blur=true);
+				for e in c{
+					e.render(ui,dummy,&ctx,animate_frame);
+				}
+			},
+			MFMElement::Ruby(base,rt) => {
+				ui.vertical(|ui|{
+					let ctx=ctx.child(|ctx|ctx.small=true);
+					ui.horizontal(|ui|{
+						Self::render_text(ui,rt,&ctx);
+					});
+					ui.horizontal(|ui|{
+						for e in base{
+							e.render(ui,dummy,&ctx,animate_frame);
+						}
+					});
+				});
+			},
 		}
 	}
 }
-#[derive(Debug)]
+#[derive(Clone,Debug)]
 pub struct Emoji{
 	id:EmojiId,
 	img:Arc<UrlImage>,
@@ -585,6 +1143,44 @@ impl EmojiId{
 	}
 }
 impl Emoji{
+	pub async fn from_reaction(
+		reaction:&str,
+		reaction_emojis:&HashMap<String,String>,
+		emoji_cache:&EmojiCache,
+	)->Option<Self>{
+		if reaction.ends_with("@.:"){//isLocalEmoji
+			let id=reaction[1..reaction.len()-3].to_string();
+			let url=emoji_cache.local_emojis.get(&id);
+			if let Some(url)=url{
+				let id=EmojiId::Local(id);
+				Some(emoji_cache.load(id,url.as_str()).await)
+			}else{
+				println!("ローカル絵文字が見つからない?{}",id);
+				None
+			}
+		}else if reaction.contains("@"){
+			//リモート絵文字
+			let id=reaction[1..reaction.len()-1].to_string();
+			let url=reaction_emojis.get(&id);
+			if let Some(url)=url{
+				let id=EmojiId::Remote(id);
+				Some(emoji_cache.load(id,url.as_str()).await)
+			}else{
+				println!("リモート絵文字が見つからない?{}",id);
+				None
+			}
+		}else{
+			//おそらくUnicode絵文字
+			//let id=hex::encode(reaction.0.as_bytes());
+			if let Some((c,url))=unicode_to_emoji(&reaction,&emoji_cache.local_instance){
+				let id=EmojiId::Unicode(c);
+				Some(emoji_cache.load(id,url.as_str()).await)
+			}else{
+				println!("Unicode絵文字が見つからない?{}",reaction);
+				None
+			}
+		}
+	}
 	pub fn image(&self,animate_frame:u64)->Option<egui::Image<'static>>{
 		self.img.get(animate_frame)
 	}
@@ -595,6 +1191,174 @@ impl Emoji{
 		&self.img
 	}
 }
+struct MFMBuilder<'a>{
+	known_emojis:Option<&'a HashMap<String,String>>,
+	instance_str:String,
+	host:Option<String>,
+	emoji_cache:&'a EmojiCache,
+	emoji_match:regex::Regex,
+}
+impl <'a> MFMBuilder<'a>{
+	fn new(
+		known_emojis: Option<&'a HashMap<String,String>>,
+		instance:Option<impl AsRef<FediverseInstance>>,
+		emoji_cache:&'a EmojiCache,
+	)->Self{
+		let known_emojis=if instance.is_none(){
+			Some(emoji_cache.local_emojis.as_ref())
+		}else{
+			known_emojis
+		};
+		let host=instance.map(|s|s.as_ref().host.clone());
+		let instance_str=host.as_ref().map(|s|format!("@{}",s)).unwrap_or_default();
+		Self{
+			known_emojis,
+			instance_str,
+			host,
+			emoji_cache,
+			emoji_match:regex::Regex::new(r#"\p{Emoji}"#).unwrap(),
+		}
+	}
+	//utf絵文字の処理
+	async fn text(&self,render:&mut Vec<MFMElement>,s:&str){
+		let mut offset=0;
+		for m in self.emoji_match.find_iter(s){
+			if m.len()==1{
+				continue;
+			}
+			if let Some((id,url))=unicode_to_emoji(m.as_str(),&self.emoji_cache.local_instance){
+				if offset<m.start(){
+					render.push(MFMElement::Text(s[offset..m.start()].to_owned()));
+				}
+				let img=self.emoji_cache.load(EmojiId::Unicode(id),url.as_str()).await;
+				render.push(MFMElement::Emoji(img));
+				offset=m.end();
+			}
+		}
+		if offset<s.len(){
+			render.push(MFMElement::Text(s[offset..].to_owned()));
+		}
+	}
+	async fn emoji(&self,render:&mut Vec<MFMElement>,code:&str){
+		let url=self.known_emojis.and_then(|emojis|emojis.get(code));
+		if let Some(url)=url{
+			let id=format!("{}{}",code,self.instance_str);
+			let id=if self.instance_str.is_empty(){
+				EmojiId::Local(id)
+			}else{
+				EmojiId::Remote(id)
+			};
+			let img=self.emoji_cache.load(id,url.as_str()).await;
+			render.push(MFMElement::Emoji(img));
+		}else{
+			render.push(MFMElement::Text(format!(":{}:",code)));
+		}
+	}
+	fn build<'b>(&'b self,nodes:Vec<MfmNode>)->futures::future::BoxFuture<'b,Vec<MFMElement>>{
+		use futures::FutureExt;
+		async move{
+			let mut render=vec![];
+			for node in nodes{
+				match node{
+					MfmNode::Text(s)=>self.text(&mut render,&s).await,
+					MfmNode::EmojiCode(code)=>self.emoji(&mut render,&code).await,
+					MfmNode::Url(url)=>render.push(MFMElement::Url(url,Arc::new(Mutex::new(None)))),
+					MfmNode::Link{silent,url,label}=>{
+						let label=self.build(label).await;
+						let summaly=if silent{
+							None
+						}else{
+							Some(Arc::new(Mutex::new(None)))
+						};
+						render.push(MFMElement::Link(url,label,summaly));
+					},
+					MfmNode::Mention{acct,username,host}=>{
+						//ホスト省略時はノートのサーバーのユーザー
+						let host=host.or_else(||self.host.clone());
+						render.push(MFMElement::Mention{acct,username,host});
+					},
+					MfmNode::Hashtag(tag)=>render.push(MFMElement::Hashtag(tag)),
+					MfmNode::InlineCode(code)=>render.push(MFMElement::InlineCode(code)),
+					MfmNode::CodeBlock{code,..}=>render.push(MFMElement::CodeBlock(code)),
+					MfmNode::Bold(c)=>render.push(MFMElement::Bold(self.build(c).await)),
+					MfmNode::Italic(c)=>render.push(MFMElement::Italic(self.build(c).await)),
+					MfmNode::Strike(c)=>render.push(MFMElement::Strike(self.build(c).await)),
+					MfmNode::Small(c)=>render.push(MFMElement::Small(self.build(c).await)),
+					MfmNode::Center(c)=>render.push(MFMElement::Center(self.build(c).await)),
+					MfmNode::Quote(c)=>render.push(MFMElement::Quote(self.build(c).await)),
+					MfmNode::Search(q)=>render.push(MFMElement::Search(q)),
+					MfmNode::Fn{name,args,children}=>{
+						self.function(&mut render,name,args,children).await;
+					},
+				}
+			}
+			render
+		}.boxed()
+	}
+	async fn function(&self,render:&mut Vec<MFMElement>,name:String,args:Vec<(String,Option<String>)>,children:Vec<MfmNode>){
+		let arg=|key:&str|args.iter().find(|(k,_)|k==key).map(|(_,v)|v.clone().unwrap_or_default());
+		match name.as_str(){
+			"x2"=>render.push(MFMElement::Scale(2f32,self.build(children).await)),
+			"x3"=>render.push(MFMElement::Scale(3f32,self.build(children).await)),
+			"x4"=>render.push(MFMElement::Scale(4f32,self.build(children).await)),
+			"fg"|"bg"=>{
+				let color=arg("color").and_then(|c|parse_color(&c));
+				let children=self.build(children).await;
+				match color{
+					Some(c) if name=="fg"=>render.push(MFMElement::Fg(c,children)),
+					Some(c)=>render.push(MFMElement::Bg(c,children)),
+					None=>render.extend(children),
+				}
+			},
+			"flip"=>{
+				let h=arg("h").is_some();
+				let v=arg("v").is_some();
+				//引数なしは左右反転
+				let h=h||!v;
+				render.push(MFMElement::Flip(h,v,self.build(children).await));
+			},
+			"blur"=>render.push(MFMElement::Blur(self.build(children).await)),
+			"ruby"=>{
+				let mut base=self.build(children).await;
+				//最後の空白以降をルビとする
+				let rt=match base.last_mut(){
+					Some(MFMElement::Text(s))=>{
+						match s.trim_end().rfind(|c:char|c.is_whitespace()){
+							Some(idx)=>{
+								let rt=s[idx..].trim().to_owned();
+								s.truncate(idx);
+								let empty=s.is_empty();
+								if empty{
+									base.pop();
+								}
+								Some(rt)
+							},
+							None=>None,
+						}
+					},
+					_=>None,
+				};
+				match rt{
+					Some(rt)=>render.push(MFMElement::Ruby(base,rt)),
+					None=>render.extend(base),
+				}
+			},
+			//未対応の関数は中身だけ表示する
+			_=>render.extend(self.build(children).await),
+		}
+	}
+}
+fn parse_color(s:&str)->Option<Color32>{
+	let v=u32::from_str_radix(s,16).ok()?;
+	let (r,g,b,a)=match s.len(){
+		3=>((v>>8&0xf)*17,(v>>4&0xf)*17,(v&0xf)*17,255),
+		4=>((v>>12&0xf)*17,(v>>8&0xf)*17,(v>>4&0xf)*17,(v&0xf)*17),
+		6=>(v>>16&0xff,v>>8&0xff,v&0xff,255),
+		8=>(v>>24&0xff,v>>16&0xff,v>>8&0xff,v&0xff),
+		_=>return None,
+	};
+	Some(Color32::from_rgba_unmultiplied(r as u8,g as u8,b as u8,a as u8))
+}
 impl MFMString{
 	pub fn is_empty(&self)->bool{
 		self.raw.is_empty()||self.render.is_empty()
@@ -617,113 +1381,54 @@ impl MFMString{
 		instance:Option<impl AsRef<FediverseInstance>>,
 		emoji_cache:&EmojiCache,
 	)->Self{
-		let known_emojis=if instance.is_none(){
-			Some(emoji_cache.local_emojis.as_ref())
-		}else{
-			known_emojis
-		};
-		let instance_str=instance.map(|s|format!("@{}",s.as_ref().host)).unwrap_or_default();
-		//println!("raw={}",raw);
-		let mut render=vec![];
-		let mut emoji_indexs=vec![];
-		if let Some(emojis)=known_emojis{
-			for (id,url) in emojis{
-				let mut s=raw.as_str();
-				let mut offset=0;
-				loop{
-					let k=format!(":{}:",id);
-					if let Some(idx)=s.find(&k){
-						let len=k.len();
-						let id=format!("{}{}",&id,instance_str);
-						let id=if instance_str.is_empty(){
-							EmojiId::Local(id)
-						}else{
-							EmojiId::Remote(id)
-						};
-						emoji_indexs.push((idx+offset,id,len,url.to_string()));
-						offset=offset+idx+len;
-						s=&raw[offset..];
-					}else{
-						break;
-					}
-				}
-			}
-		}
-		let url_match=regex::Regex::new("https?://[0-9A-Za-z_\\./\\?\\&=%#\\-\\+\\!\\:\\,@]+").unwrap();
-		let emoji_match=regex::Regex::new(r#"\p{Emoji}"#).unwrap();
-		for m in emoji_match.find_iter(&raw){
-			if m.len()==1{
-				continue;
-			}
-			if let Some((id,url))=unicode_to_emoji(m.as_str(),&emoji_cache.local_instance){
-				emoji_indexs.push((m.start(),EmojiId::Unicode(id),m.len(),url));
-				//println!("{}..{}\t{}",m.start(),m.len(),m.as_str());
-			}
-		}
-		//utf絵文字の処理
-		emoji_indexs.sort_by(|(a,_,_,_),(b,_,_,_)|a.partial_cmp(b).unwrap());
-		fn push_str(url_match:&regex::Regex,render:&mut Vec<MFMElement>,s:&str){
-			let mut offset=0;
-			for m in url_match.find_iter(s){
-				if m.start()!=offset{
-					render.push(MFMElement::Text(s[offset..m.start()].to_owned()));
-				}
-				render.push(MFMElement::Url(m.as_str().to_owned(),Arc::new(Mutex::new(None))));
-				offset=m.end();
-			}
-			let s=&s[offset..];
-			if !s.is_empty(){
-				render.push(MFMElement::Text(s.to_owned()));
-			}
-		}
-		let mut offset=0;
-		for (idx,id,skip_chars,url) in emoji_indexs{
-			if offset<idx{
-				let s=&raw[offset..idx];
-				push_str(&url_match,&mut render,s);
-			}
-			offset=idx+skip_chars;
-			let img=emoji_cache.load(id,url.as_str()).await;
-			render.push(MFMElement::Emoji(img));
-		}
-		if offset<raw.len(){
-			let s=&raw[offset..];
-			push_str(&url_match,&mut render,s);
+		let builder=MFMBuilder::new(known_emojis,instance,emoji_cache);
+		let render=builder.build(mfm::parse(&raw)).await;
+		Self{
+			raw,
+			render,
 		}
-		//println!("{:?}",render);
+	}
+	//表示名など絵文字以外の構文を解釈しない文字列
+	pub async fn new_simple(
+		raw:String,
+		known_emojis: Option<&HashMap<String,String>>,
+		instance:Option<impl AsRef<FediverseInstance>>,
+		emoji_cache:&EmojiCache,
+	)->Self{
+		let builder=MFMBuilder::new(known_emojis,instance,emoji_cache);
+		let render=builder.build(mfm::parse_simple(&raw)).await;
 		Self{
 			raw,
 			render,
 		}
 	}
 	pub fn emojis(&self)->impl Iterator<Item=&Arc<UrlImage>>{
-		self.render.iter().map(|s|{
-			match s{
-				MFMElement::Emoji(img)=>{
-					Some(&img.img)
-				},
-				_=>None
-			}
-		}).filter(|s|s.is_some()).map(|s|s.unwrap())
+		let mut emojis=vec![];
+		for e in &self.render{
+			e.walk(&mut |e|{
+				if let MFMElement::Emoji(img)=e{
+					emojis.push(&img.img);
+				}
+			});
+		}
+		emojis.into_iter()
 	}
 	pub fn urls(&self)->impl Iterator<Item=(&String,&Arc<Mutex<Option<Summaly>>>)>{
-		self.render.iter().map(|s|{
-			match s{
-				MFMElement::Url(s,r)=>{
-					Some((s,r))
-				},
-				_=>None
-			}
-		}).filter(|s|s.is_some()).map(|s|s.unwrap())
+		let mut urls=vec![];
+		for e in &self.render{
+			e.walk(&mut |e|{
+				match e{
+					MFMElement::Url(s,r)=>urls.push((s,r)),
+					MFMElement::Link(s,_,Some(r))=>urls.push((s,r)),
+					_=>{}
+				}
+			});
+		}
+		urls.into_iter()
 	}
 	pub fn render(&self,ui:&mut egui::Ui,strong:bool,dummy:&UrlImage,animate_frame:u64){
-		ui.horizontal_wrapped(|ui|{
-			ui.spacing_mut().item_spacing=[0f32,0f32].into();
-			let mut ctx=MFMRenderContext::new();
-			for r in &self.render{
-				r.render(ui,strong,dummy,&mut ctx,animate_frame);
-			}
-		});
+		let ctx=MFMRenderContext::new(strong);
+		render_elements(ui,&self.render,dummy,&ctx,animate_frame);
 	}
 }
 #[derive(Debug)]
@@ -768,7 +1473,7 @@ impl  FediverseInstance{
 			host:"".to_owned(),
 			display_name:name.clone().unwrap_or_default(),
 			theme_color,
-			icon:icon_url.into()
+			icon:(icon_url,ImageSize::Icon).into()
 		}
 	}
 	pub fn host(&self)->&str{
@@ -797,42 +1502,46 @@ impl Debug for TextureState{
 }
 impl TextureState{
 	fn take_memory(&mut self)->Option<Vec<(u32,egui::ColorImage)>>{
-		if let Self::OnMemory(_)=self{
-
+		let t=if let Self::OnMemory(t)=self{
+			std::mem::take(t)
 		}else{
 			return None;
-		}
-		let t=std::mem::take(self);
-		match t{
-			TextureState::OnMemory(t) => Some(t),
-			TextureState::OnGpu(_) => None,
-			TextureState::None => None,
-		}
+		};
+		*self=Self::None;
+		Some(t)
 	}
 	fn take_gpu(&mut self)->Option<Vec<(u32,egui::TextureHandle)>>{
-		if let Self::OnGpu(_)=self{
-
+		let t=if let Self::OnGpu(t)=self{
+			std::mem::take(t)
 		}else{
 			return None;
-		}
-		let t=std::mem::take(self);
-		match t{
-			TextureState::OnMemory(_) => None,
-			TextureState::OnGpu(t) => Some(t),
-			TextureState::None => None,
+		};
+		*self=Self::None;
+		texture_released(&t);
+		Some(t)
+	}
+}
+fn texture_bytes(tex:&[(u32,egui::TextureHandle)])->u64{
+	tex.iter().map(|(_,h)|{
+		let [w,h]=h.size();
+		(w*h*4) as u64
+	}).sum()
+}
+fn texture_released(tex:&[(u32,egui::TextureHandle)]){
+	GPU_BYTES.fetch_sub(texture_bytes(tex),std::sync::atomic::Ordering::Relaxed);
+	GPU_TEXTURES.fetch_sub(tex.len() as u64,std::sync::atomic::Ordering::Relaxed);
+}
+impl Drop for TextureState{
+	fn drop(&mut self){
+		if let Self::OnGpu(tex)=self{
+			texture_released(tex);
 		}
 	}
 }
 impl From<Vec<(u32,DynamicImage)>> for TextureState{
 	fn from(img: Vec<(u32,DynamicImage)>) -> Self {
 		let eimg=img.into_iter().map(|(timestamp,img)|{
-			let size=[img.width() as _, img.height() as _];
-			let image_buffer = img.into_rgba8();
-			let pixels = image_buffer.as_flat_samples();
-			(timestamp,egui::ColorImage::from_rgba_unmultiplied(
-				size,
-				pixels.as_slice(),
-			))
+			(timestamp,image_decode::to_color_image(img))
 		}).collect();
 		TextureState::OnMemory(eimg)
 	}
@@ -847,12 +1556,28 @@ pub struct UrlImage{
 	url:String,
 	img:RwLock<TextureState>,
 	loaded:AtomicBool,
+	//最後に描画したフレーム
+	last_used:AtomicU64,
+	evicted:AtomicBool,
+	//縮小の上限
+	size:ImageSize,
+	//添付ファイルを次に読み込む時の幅と読み込んだ時の幅(物理ピクセル)
+	media_width:AtomicU32,
+	decoded_width:AtomicU32,
 }
 impl From<String> for UrlImage{
 	fn from(url:String) -> Self {
 		Self::new(url,TextureState::None)
 	}
 }
+impl From<(String,ImageSize)> for UrlImage{
+	fn from((url,size):(String,ImageSize)) -> Self {
+		Self{
+			size,
+			..Self::new(url,TextureState::None)
+		}
+	}
+}
 impl UrlImage{
 	fn new(url:String,img:TextureState) -> Self {
 		let loaded=if let TextureState::OnMemory(_)=&img{
@@ -864,8 +1589,32 @@ impl UrlImage{
 			url,
 			img:RwLock::new(img),
 			loaded:AtomicBool::new(loaded),
+			last_used:AtomicU64::new(0),
+			evicted:AtomicBool::new(false),
+			size:ImageSize::Media,
+			media_width:AtomicU32::new(image_decode::DEFAULT_MEDIA_WIDTH_PX),
+			decoded_width:AtomicU32::new(0),
 		}
 	}
+	//表示幅が読み込んだ時の幅を超えたら読み直しが必要なのでtrue
+	pub fn request_width(&self,px:f32)->bool{
+		if self.size!=ImageSize::Media{
+			return false;
+		}
+		let px=px.ceil() as u32;
+		self.media_width.fetch_max(px,std::sync::atomic::Ordering::Relaxed);
+		//読み込み中なら読み込む時点の幅が使われる
+		let decoded=self.decoded_width.load(std::sync::atomic::Ordering::Relaxed);
+		if !self.loaded()||decoded==0||decoded>=px{
+			return false;
+		}
+		self.loaded.store(false,std::sync::atomic::Ordering::Relaxed);
+		true
+	}
+	//拡大表示用に同じURLを元の解像度で読み直す
+	pub fn full_resolution(&self)->Self{
+		(self.url.clone(),ImageSize::Original).into()
+	}
 	pub fn size(&self)->Option<[usize;2]>{
 		let r_lock=self.img.blocking_read();
 		if let TextureState::OnGpu(h)=&*r_lock{
@@ -878,6 +1627,7 @@ impl UrlImage{
 	pub fn get(&self,animate_ms:u64)->Option<egui::Image<'static>>{
 		let r_lock=self.img.blocking_read();
 		if let TextureState::OnGpu(h)=&*r_lock{
+			self.last_used.store(FRAME.load(std::sync::atomic::Ordering::Relaxed),std::sync::atomic::Ordering::Relaxed);
 			let animate_ms=animate_ms as usize;
 			let last=h.last()?;
 			let animate_ms=if last.0>0{
@@ -920,9 +1670,39 @@ impl UrlImage{
 			None=>None
 		};
 		if let Some(h)=handle{
+			GPU_BYTES.fetch_add(texture_bytes(&h),std::sync::atomic::Ordering::Relaxed);
+			GPU_TEXTURES.fetch_add(h.len() as u64,std::sync::atomic::Ordering::Relaxed);
+			self.last_used.store(FRAME.load(std::sync::atomic::Ordering::Relaxed),std::sync::atomic::Ordering::Relaxed);
 			*r=TextureState::OnGpu(h);
 		}
 	}
+	//しばらく描画していないテクスチャを捨てて次に必要になった時に読み直す
+	pub fn evict(&self)->bool{
+		if self.url.as_str()==DUMMY_PNG{
+			return false;
+		}
+		let frame=FRAME.load(std::sync::atomic::Ordering::Relaxed);
+		if self.last_used.load(std::sync::atomic::Ordering::Relaxed)+TEXTURE_KEEP_FRAMES>frame{
+			return false;
+		}
+		let mut w=match self.img.try_write(){
+			Ok(w)=>w,
+			Err(_)=>return false,
+		};
+		if let TextureState::OnGpu(_)=&*w{
+			*w=TextureState::None;
+			self.loaded.store(false,std::sync::atomic::Ordering::Relaxed);
+			self.evicted.store(true,std::sync::atomic::Ordering::Relaxed);
+			EVICT_GEN.fetch_add(1,std::sync::atomic::Ordering::Relaxed);
+			true
+		}else{
+			false
+		}
+	}
+	//退避されて読み直していない
+	pub fn is_evicted(&self)->bool{
+		self.evicted.load(std::sync::atomic::Ordering::Relaxed)
+	}
 	pub fn loaded(&self)->bool{
 		self.loaded.load(std::sync::atomic::Ordering::Relaxed)
 	}
@@ -933,6 +1713,11 @@ impl UrlImage{
 			url:DUMMY_PNG.to_owned(),
 			loaded:AtomicBool::new(true),
 			img,
+			last_used:AtomicU64::new(0),
+			evicted:AtomicBool::new(false),
+			size:ImageSize::Media,
+			media_width:AtomicU32::new(image_decode::DEFAULT_MEDIA_WIDTH_PX),
+			decoded_width:AtomicU32::new(0),
 		}
 	}
 	pub async fn load(&self,client:&reqwest::Client){
@@ -950,86 +1735,70 @@ impl UrlImage{
 			self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
 			return;
 		}
-		let resource_id=uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_URL,self.url.as_bytes());
-		let cache_dir=self::cache_dir();
-		if !tokio::fs::try_exists(&cache_dir).await.unwrap_or(true){
-			println!("create_dir_all {:?}",tokio::fs::create_dir_all(&cache_dir).await);
-		}
-		let cache_file=std::path::Path::new(&cache_dir).to_path_buf().join(resource_id.to_string());
-		if tokio::fs::try_exists(&cache_file).await.unwrap_or(false){
-			if let Ok(mut f)=tokio::fs::File::open(&cache_file).await{
-				let mut buf=vec![];
-				if f.read_to_end(&mut buf).await.is_ok(){
-					println!("GET CACHE-HIT {}",cache_file.as_os_str().to_string_lossy());
-					self.load_bytes(&buf).await;
-					return;
-				}
-			}
-		}
-		eprintln!("GET {}",self.url);
-		if let Ok(icon_data)=client.get(&self.url).send().await{
-			if !icon_data.status().is_success(){
-				eprintln!("Remote status {} {}",icon_data.status(),self.url);
-				self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
+		let cache=disk_cache::disk_cache();
+		let (stale,etag,last_modified)=match cache.lookup(&self.url).await{
+			CacheLookup::Fresh(buf)=>{
+				println!("GET CACHE-HIT {}",self.url);
+				self.load_bytes(&buf).await;
 				return;
-			}
-			let mut immutable=false;
-			if let Some(Ok(cc))=icon_data.headers().get(reqwest::header::CACHE_CONTROL).map(|s|s.to_str()){
-				if cc.contains("immutable"){
-					immutable=true;
+			},
+			CacheLookup::Stale(buf,etag,last_modified)=>(Some(buf),etag,last_modified),
+			CacheLookup::Miss=>(None,None,None),
+		};
+		eprintln!("GET {}",self.url);
+		let mut req=client.get(&self.url);
+		if let Some(etag)=etag{
+			req=req.header(reqwest::header::IF_NONE_MATCH,etag);
+		}
+		if let Some(last_modified)=last_modified{
+			req=req.header(reqwest::header::IF_MODIFIED_SINCE,last_modified);
+		}
+		match req.send().await{
+			Ok(icon_data)=>{
+				if icon_data.status()==reqwest::StatusCode::NOT_MODIFIED{
+					if let Some(buf)=stale{
+						println!("GET CACHE-REVALIDATED {}",self.url);
+						cache.revalidated(&self.url,icon_data.headers()).await;
+						self.load_bytes(&buf).await;
+						return;
+					}
 				}
-			}
-			if let Ok(icon)=icon_data.bytes().await{
-				//ローカルにキャッシュする
-				if immutable&&icon.len()<1*1024*1024{//1ファイル1MB上限
-					if let Ok(mut f)=tokio::fs::File::create(&cache_file).await{
-						if f.write_all(&icon).await.is_err(){
-							drop(f);
-							let _=tokio::fs::remove_file(&cache_file).await;
-						}else{
-							println!("GET CACHE-WRITE {}",cache_file.as_os_str().to_string_lossy());
-						}
+				if !icon_data.status().is_success(){
+					eprintln!("Remote status {} {}",icon_data.status(),self.url);
+					self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
+					return;
+				}
+				let headers=icon_data.headers().clone();
+				if let Ok(icon)=icon_data.bytes().await{
+					//ローカルにキャッシュする
+					if cache.store(&self.url,&icon,&headers).await{
+						println!("GET CACHE-WRITE {}",self.url);
 					}
+					self.load_bytes(&icon).await;
 				}
-				self.load_bytes(&icon).await;
-			}
+			},
+			Err(e)=>{
+				//繋がらなければ期限切れでも使う
+				if let Some(buf)=stale{
+					eprintln!("{:?} use stale cache",e);
+					self.load_bytes(&buf).await;
+				}
+			},
 		}
 	}
 	async fn load_bytes(&self,icon:&[u8]){
-		match image::guess_format(&icon){
-			Ok(codec)=>{
-				if let image::ImageFormat::WebP=codec{
-					let decoder=webp::AnimDecoder::new(&icon);
-					match decoder.decode(){
-						Ok(image) => {
-							*self.img.write().await=image.into_iter().map(|frame|{
-								(frame.get_time_ms() as u32,Into::<image::DynamicImage>::into(&frame))
-							}).collect::<Vec<_>>().into();
-							self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
-							return;
-						},
-						Err(e) => {
-							eprintln!("{:?}",e);
-						},
-					}
-				}
-				match image::load_from_memory_with_format(&icon,codec){
-					Ok(img)=>{
-						*self.img.write().await=vec![(0,img)].into();
-						self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
-					},
-					Err(e)=>{
-						eprintln!("{}",codec.to_mime_type());
-						eprintln!("{:?} {}",e,self.url);
-						self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
-					}
-				}
+		let media_width=self.media_width.load(std::sync::atomic::Ordering::Relaxed);
+		match image_decode::decode(icon.to_vec(),self.size,media_width).await{
+			Ok(frames)=>{
+				*self.img.write().await=TextureState::OnMemory(frames);
+				self.decoded_width.store(media_width,std::sync::atomic::Ordering::Relaxed);
 			},
 			Err(e)=>{
-				eprintln!("{:?} {}",e,self.url);
-				self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
+				eprintln!("{} {}",e,self.url);
 			}
 		}
+		self.loaded.store(true, std::sync::atomic::Ordering::Relaxed);
+		self.evicted.store(false,std::sync::atomic::Ordering::Relaxed);
 	}
 	pub async fn unload(&self){
 		let mut wl=self.img.write().await;
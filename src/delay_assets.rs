@@ -9,6 +9,7 @@ pub(crate) async fn delay_assets(mut recv:Receiver<data_model::DelayAssets>,ctx:
 	//tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
 	let mut note_buf=Vec::with_capacity(4);
 	let mut job_buf=Vec::with_capacity(4);
+	let mut user_job_buf=Vec::with_capacity(4);
 	let mut emoji_job_buf=Vec::with_capacity(4);
 	let mut image_job_buf=Vec::with_capacity(32);
 	let mut state=Arc::new(StateFile::load().unwrap_or_default());
@@ -24,6 +25,7 @@ pub(crate) async fn delay_assets(mut recv:Receiver<data_model::DelayAssets>,ctx:
 					let client=client.clone();
 					let config=config.clone();
 					let q=note.quote.clone();
+					let r=note.reply.clone();
 					let state=state.clone();
 					job_buf.push(async move{
 						futures::join!(
@@ -32,10 +34,23 @@ pub(crate) async fn delay_assets(mut recv:Receiver<data_model::DelayAssets>,ctx:
 									load_note(note,&ctx,&client,&config,&state).await
 								}
 							},
+							async{
+								if let Some(note)=r{
+									load_note(note,&ctx,&client,&config,&state).await
+								}
+							},
 							load_note(note,&ctx,&client,&config,&state)
 						);
 					});
 				},
+				data_model::DelayAssets::User(user) => {
+					let ctx=ctx.clone();
+					let client=client.clone();
+					let config=config.clone();
+					user_job_buf.push(async move{
+						load_user(user,&ctx,&client,&config).await;
+					});
+				},
 				data_model::DelayAssets::Emoji(cache,emoji) => {
 					let ctx=ctx.clone();
 					let client=client.clone();
@@ -67,12 +82,14 @@ pub(crate) async fn delay_assets(mut recv:Receiver<data_model::DelayAssets>,ctx:
 			}
 		}
 		let job_buf:Vec<_>=job_buf.drain(..).collect();
+		let user_job_buf:Vec<_>=user_job_buf.drain(..).collect();
 		let emoji_job_buf:Vec<_>=emoji_job_buf.drain(..).collect();
 		let image_job_buf:Vec<_>=image_job_buf.drain(..).collect();
 		let ctx=ctx.clone();
 		tokio::runtime::Handle::current().spawn(async move{
 			futures::join!(
 				futures::future::join_all(job_buf),
+				futures::future::join_all(user_job_buf),
 				futures::future::join_all(emoji_job_buf),
 				futures::future::join_all(image_job_buf),
 			);
@@ -83,26 +100,8 @@ pub(crate) async fn delay_assets(mut recv:Receiver<data_model::DelayAssets>,ctx:
 	//user.icon.unload().await;
 }
 async fn load_note(note:Arc<data_model::Note>,ctx:&egui::Context,client:&Client,config:&Arc<ConfigFile>,state:&Arc<StateFile>){
-	let mut job_buf=Vec::with_capacity(4);
-	let mut job_buf2=Vec::with_capacity(4);
 	let mut job_buf_emojis=Vec::with_capacity(32);
 	let mut job_buf_urls=Vec::new();
-	if let Some(instance)=note.user.instance.clone(){
-		if !instance.icon.loaded(){
-			let client=client.clone();
-			let ctx=ctx.clone();
-			let config=config.clone();
-			job_buf2.push(async move{
-				instance.icon.load(&client).await;
-				instance.icon.load_gpu(&ctx,&config).await;
-			});
-		}
-	}
-	for emoji in note.user.display_name.emojis(){
-		if !emoji.loaded(){
-			job_buf_emojis.push(load_url(emoji.clone(),client.clone(),ctx.clone(),config.clone()));
-		}
-	}
 	for emoji in note.text.emojis(){
 		if !emoji.loaded(){
 			job_buf_emojis.push(load_url(emoji.clone(),client.clone(),ctx.clone(),config.clone()));
@@ -160,20 +159,45 @@ async fn load_note(note:Arc<data_model::Note>,ctx:&egui::Context,client:&Client,
 			}
 		});
 	}
-	if !note.user.icon.loaded(){
+	futures::join!(
+		load_user(note.user.clone(),ctx,client,config),
+		futures::future::join_all(job_buf_emojis.drain(..)),
+		futures::future::join_all(job_buf_urls.drain(..)),
+	);
+}
+async fn load_user(user:Arc<data_model::UserProfile>,ctx:&egui::Context,client:&Client,config:&Arc<ConfigFile>){
+	let mut job_buf=Vec::with_capacity(4);
+	let mut job_buf2=Vec::with_capacity(4);
+	let mut job_buf_emojis=Vec::with_capacity(32);
+	if let Some(instance)=user.instance.clone(){
+		if !instance.icon.loaded(){
+			let client=client.clone();
+			let ctx=ctx.clone();
+			let config=config.clone();
+			job_buf2.push(async move{
+				instance.icon.load(&client).await;
+				instance.icon.load_gpu(&ctx,&config).await;
+			});
+		}
+	}
+	for emoji in user.display_name.emojis(){
+		if !emoji.loaded(){
+			job_buf_emojis.push(load_url(emoji.clone(),client.clone(),ctx.clone(),config.clone()));
+		}
+	}
+	if !user.icon.loaded(){
 		let client=client.clone();
 		let ctx=ctx.clone();
 		let config=config.clone();
 		job_buf.push(async move{
-			note.user.icon.load(&client).await;
-			note.user.icon.load_gpu(&ctx,&config).await;
+			user.icon.load(&client).await;
+			user.icon.load_gpu(&ctx,&config).await;
 		});
 	}
 	futures::join!(
 		futures::future::join_all(job_buf.drain(..)),
 		futures::future::join_all(job_buf2.drain(..)),
 		futures::future::join_all(job_buf_emojis.drain(..)),
-		futures::future::join_all(job_buf_urls.drain(..)),
 	);
 }
 async fn load_url(emoji:Arc<data_model::UrlImage>,client: reqwest::Client,ctx: egui::Context,config:Arc<ConfigFile>){
@@ -0,0 +1,400 @@
+use std::{collections::HashMap, path::PathBuf, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, OnceLock}, time::Duration};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::data_model;
+
+pub const DEFAULT_CACHE_SIZE_MB:u32=512;
+const INDEX_FILE:&str="index.json";
+const INDEX_TMP_FILE:&str="index.json.tmp";
+//索引の書き込みをまとめる間隔
+const INDEX_SAVE_DELAY:Duration=Duration::from_secs(3);
+static DISK_CACHE:OnceLock<DiskCache>=OnceLock::new();
+pub(crate) fn disk_cache()->&'static DiskCache{
+	DISK_CACHE.get_or_init(DiskCache::new)
+}
+//画像のキャッシュ
+pub(crate) struct DiskCache{
+	dir:PathBuf,
+	//起動時に裏で読み込む。間に合わなければ初回アクセスで読む
+	index:std::sync::Mutex<Option<CacheIndex>>,
+	max_size:AtomicU64,
+	save_pending:AtomicBool,
+}
+#[derive(Serialize,Deserialize,Default)]
+struct CacheIndex{
+	entries:HashMap<String,CacheEntry>,
+}
+#[derive(Serialize,Deserialize,Clone,Debug)]
+struct CacheEntry{
+	size:u64,
+	last_access:i64,
+	//これを過ぎたら再検証する
+	expires:i64,
+	etag:Option<String>,
+	last_modified:Option<String>,
+}
+pub(crate) enum CacheLookup{
+	Fresh(Vec<u8>),
+	//再検証に使うETagとLast-Modified
+	Stale(Vec<u8>,Option<String>,Option<String>),
+	Miss,
+}
+#[derive(Clone,Copy,Debug,Default)]
+pub(crate) struct CacheStats{
+	pub(crate) entries:usize,
+	pub(crate) size:u64,
+	pub(crate) max_size:u64,
+}
+impl CacheIndex{
+	fn size(&self)->u64{
+		self.entries.values().map(|e|e.size).sum()
+	}
+	//索引が読めなければ残っているファイルから作り直す
+	//期限や再検証の情報は無いので次に使う時に取り直す
+	fn rebuild(dir:&std::path::Path)->Self{
+		let mut index=Self::default();
+		let dir=match std::fs::read_dir(dir){
+			Ok(dir)=>dir,
+			Err(_)=>return index,
+		};
+		for entry in dir.flatten(){
+			let key=match entry.file_name().to_str(){
+				Some(name) if uuid::Uuid::parse_str(name).is_ok()=>name.to_owned(),
+				_=>continue,
+			};
+			let meta=match entry.metadata(){
+				Ok(meta) if meta.is_file()=>meta,
+				_=>continue,
+			};
+			let last_access=meta.modified().ok().and_then(|t|t.duration_since(std::time::UNIX_EPOCH).ok()).map(|d|d.as_millis() as i64).unwrap_or(0);
+			index.entries.insert(key,CacheEntry{
+				size:meta.len(),
+				last_access,
+				expires:0,
+				etag:None,
+				last_modified:None,
+			});
+		}
+		index
+	}
+	//古く使われていないものから外して容量内に収める。外したキーを返す
+	fn evict(&mut self,max_size:u64)->Vec<String>{
+		let mut size=self.size();
+		let mut entries:Vec<_>=self.entries.iter().map(|(k,e)|(k.clone(),e.last_access,e.size)).collect();
+		entries.sort_by_key(|(_,last_access,_)|*last_access);
+		let mut removed=vec![];
+		for (key,_,entry_size) in entries{
+			if size<=max_size{
+				break;
+			}
+			self.entries.remove(&key);
+			size-=entry_size;
+			removed.push(key);
+		}
+		removed
+	}
+}
+impl CacheEntry{
+	//Cache-Controlなどから有効期限を決める
+	fn from_headers(size:u64,headers:&HeaderMap)->Option<Self>{
+		let header=|name|headers.get(name).and_then(|v|v.to_str().ok()).map(|v|v.to_owned());
+		let cc=header(reqwest::header::CACHE_CONTROL).unwrap_or_default();
+		if cc.contains("no-store"){
+			return None;
+		}
+		let now=chrono::Utc::now().timestamp_millis();
+		let expires=if cc.contains("immutable"){
+			i64::MAX
+		}else if let Some(max_age)=cc.split(',').find_map(|v|v.trim().strip_prefix("max-age=").and_then(|v|v.parse::<i64>().ok())){
+			now.saturating_add(max_age.saturating_mul(1000))
+		}else{
+			now
+		};
+		let etag=header(reqwest::header::ETAG);
+		let last_modified=header(reqwest::header::LAST_MODIFIED);
+		//期限も再検証の手段も無ければ残さない
+		if expires<=now&&etag.is_none()&&last_modified.is_none(){
+			return None;
+		}
+		Some(Self{
+			size,
+			last_access:now,
+			expires,
+			etag,
+			last_modified,
+		})
+	}
+}
+impl DiskCache{
+	fn new()->Self{
+		Self{
+			dir:std::path::Path::new(&data_model::cache_dir()).join("images"),
+			index:std::sync::Mutex::new(None),
+			max_size:AtomicU64::new(DEFAULT_CACHE_SIZE_MB as u64*1024*1024),
+			save_pending:AtomicBool::new(false),
+		}
+	}
+	fn key(url:&str)->String{
+		uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_URL,url.as_bytes()).simple().to_string()
+	}
+	fn file(&self,key:&str)->PathBuf{
+		self.dir.join(key)
+	}
+	pub(crate) fn set_max_size_mb(&self,mb:u32){
+		self.max_size.store(mb as u64*1024*1024,Ordering::Relaxed);
+	}
+	//UIスレッドを待たせないように起動時に別スレッドから呼ぶ
+	pub(crate) fn load_index(&self){
+		self.with_index(|_|());
+	}
+	fn read_index(&self)->CacheIndex{
+		match std::fs::read(self.file(INDEX_FILE)){
+			Ok(buf)=>match serde_json::from_slice(&buf){
+				Ok(index)=>return index,
+				Err(e)=>eprintln!("cache index {:?} rebuild",e),
+			},
+			Err(e)=>{
+				if e.kind()!=std::io::ErrorKind::NotFound{
+					eprintln!("cache index {:?} rebuild",e);
+				}
+				remove_legacy_files();
+			},
+		}
+		let index=CacheIndex::rebuild(&self.dir);
+		if !index.entries.is_empty(){
+			self.save_index();
+		}
+		index
+	}
+	fn with_index<R>(&self,f:impl FnOnce(&mut CacheIndex)->R)->R{
+		let mut lock=self.index.lock().unwrap();
+		let index=lock.get_or_insert_with(||self.read_index());
+		f(index)
+	}
+	//少し待ってからまとめて書き込む
+	fn save_index(&self){
+		if self.save_pending.swap(true,Ordering::AcqRel){
+			return;
+		}
+		std::thread::spawn(||{
+			std::thread::sleep(INDEX_SAVE_DELAY);
+			disk_cache().write_index();
+		});
+	}
+	//索引を握ったまま一時ファイルに書いて置き換える
+	fn write_index(&self){
+		let lock=self.index.lock().unwrap();
+		self.save_pending.store(false,Ordering::Release);
+		let index=match lock.as_ref(){
+			Some(index)=>index,
+			None=>return,
+		};
+		let buf=match serde_json::to_vec(index){
+			Ok(buf)=>buf,
+			Err(e)=>{
+				eprintln!("cache index {:?}",e);
+				return;
+			}
+		};
+		if !self.dir.exists(){
+			println!("create_dir_all {:?}",std::fs::create_dir_all(&self.dir));
+		}
+		let tmp=self.file(INDEX_TMP_FILE);
+		if let Err(e)=std::fs::write(&tmp,buf).and_then(|_|std::fs::rename(&tmp,self.file(INDEX_FILE))){
+			eprintln!("cache index {:?}",e);
+		}
+	}
+	pub(crate) async fn lookup(&self,url:&str)->CacheLookup{
+		let key=Self::key(url);
+		let now=chrono::Utc::now().timestamp_millis();
+		let entry=self.with_index(|index|{
+			index.entries.get_mut(&key).map(|e|{
+				e.last_access=now;
+				e.clone()
+			})
+		});
+		let entry=match entry{
+			Some(entry)=>entry,
+			None=>return CacheLookup::Miss,
+		};
+		//LRUの順番を残す
+		self.save_index();
+		match tokio::fs::read(self.file(&key)).await{
+			Ok(buf)=>{
+				if entry.expires>now{
+					CacheLookup::Fresh(buf)
+				}else{
+					CacheLookup::Stale(buf,entry.etag,entry.last_modified)
+				}
+			},
+			Err(_)=>{
+				//ファイルが消えていれば索引からも外す
+				self.with_index(|index|index.entries.remove(&key));
+				CacheLookup::Miss
+			},
+		}
+	}
+	pub(crate) async fn store(&self,url:&str,buf:&[u8],headers:&HeaderMap)->bool{
+		let entry=match CacheEntry::from_headers(buf.len() as u64,headers){
+			Some(entry)=>entry,
+			None=>return false,
+		};
+		//1ファイルで容量の大半を使わせない
+		if entry.size>self.max_size.load(Ordering::Relaxed)/4{
+			return false;
+		}
+		let key=Self::key(url);
+		if !tokio::fs::try_exists(&self.dir).await.unwrap_or(true){
+			println!("create_dir_all {:?}",tokio::fs::create_dir_all(&self.dir).await);
+		}
+		if let Err(e)=tokio::fs::write(self.file(&key),buf).await{
+			eprintln!("cache write {:?}",e);
+			let _=tokio::fs::remove_file(self.file(&key)).await;
+			return false;
+		}
+		self.with_index(|index|index.entries.insert(key,entry));
+		self.trim().await;
+		true
+	}
+	//304で返ってきたら期限を延ばす
+	pub(crate) async fn revalidated(&self,url:&str,headers:&HeaderMap){
+		let key=Self::key(url);
+		self.with_index(|index|{
+			if let Some(old)=index.entries.get_mut(&key){
+				if let Some(mut entry)=CacheEntry::from_headers(old.size,headers){
+					entry.etag=entry.etag.or(old.etag.take());
+					entry.last_modified=entry.last_modified.or(old.last_modified.take());
+					*old=entry;
+				}
+			}
+		});
+		self.save_index();
+	}
+	//古く使われていないものから消して容量内に収める
+	pub(crate) async fn trim(&self){
+		let max_size=self.max_size.load(Ordering::Relaxed);
+		let removed=self.with_index(|index|index.evict(max_size));
+		for key in &removed{
+			let _=tokio::fs::remove_file(self.file(key)).await;
+		}
+		self.save_index();
+	}
+	//読み込み中なら待たずに0件として返す
+	pub(crate) fn stats(&self)->CacheStats{
+		let (entries,size)=match self.index.try_lock().as_deref(){
+			Ok(Some(index))=>(index.entries.len(),index.size()),
+			_=>(0,0),
+		};
+		CacheStats{
+			entries,
+			size,
+			max_size:self.max_size.load(Ordering::Relaxed),
+		}
+	}
+	pub(crate) async fn clear(&self){
+		let keys:Vec<_>=self.with_index(|index|index.entries.drain().map(|(k,_)|k).collect());
+		for key in keys{
+			let _=tokio::fs::remove_file(self.file(&key)).await;
+		}
+		self.save_index();
+	}
+}
+//索引を持たない頃のキャッシュファイルを消す
+fn remove_legacy_files(){
+	let dir=match std::fs::read_dir(data_model::cache_dir()){
+		Ok(dir)=>dir,
+		Err(_)=>return,
+	};
+	for entry in dir.flatten(){
+		let path=entry.path();
+		let legacy=path.is_file()&&path.file_name().and_then(|n|n.to_str()).map(|n|uuid::Uuid::parse_str(n).is_ok()).unwrap_or(false);
+		if legacy{
+			let _=std::fs::remove_file(path);
+		}
+	}
+}
+#[cfg(test)]
+mod tests{
+	use super::*;
+
+	fn entry(size:u64,last_access:i64)->CacheEntry{
+		CacheEntry{
+			size,
+			last_access,
+			expires:0,
+			etag:None,
+			last_modified:None,
+		}
+	}
+	fn index(entries:&[(&str,u64,i64)])->CacheIndex{
+		CacheIndex{
+			entries:entries.iter().map(|(k,size,last_access)|(k.to_string(),entry(*size,*last_access))).collect(),
+		}
+	}
+	fn headers(list:&[(reqwest::header::HeaderName,&str)])->HeaderMap{
+		let mut headers=HeaderMap::new();
+		for (name,value) in list{
+			headers.insert(name.clone(),value.parse().unwrap());
+		}
+		headers
+	}
+	#[test]
+	fn evict_oldest_first(){
+		let mut index=index(&[("new",10,300),("old",10,100),("mid",10,200)]);
+		assert_eq!(index.evict(15),vec!["old".to_owned(),"mid".to_owned()]);
+		assert_eq!(index.entries.keys().collect::<Vec<_>>(),vec!["new"]);
+	}
+	#[test]
+	fn evict_within_budget(){
+		let mut index=index(&[("a",10,100),("b",20,200)]);
+		assert!(index.evict(30).is_empty());
+		assert_eq!(index.entries.len(),2);
+		//1件外せば収まるならそれ以上は消さない
+		assert_eq!(index.evict(25),vec!["a".to_owned()]);
+		assert_eq!(index.size(),20);
+	}
+	#[test]
+	fn evict_all_when_budget_zero(){
+		let mut index=index(&[("a",10,100),("b",20,200)]);
+		assert_eq!(index.evict(0).len(),2);
+		assert_eq!(index.size(),0);
+	}
+	#[test]
+	fn max_age_sets_expiry(){
+		let now=chrono::Utc::now().timestamp_millis();
+		let e=CacheEntry::from_headers(1,&headers(&[(reqwest::header::CACHE_CONTROL,"public, max-age=60")])).unwrap();
+		assert!(e.expires>=now+60_000&&e.expires<now+70_000);
+		let e=CacheEntry::from_headers(1,&headers(&[(reqwest::header::CACHE_CONTROL,"max-age=31536000, immutable")])).unwrap();
+		assert_eq!(e.expires,i64::MAX);
+	}
+	#[test]
+	fn keeps_validators_without_max_age(){
+		let e=CacheEntry::from_headers(1,&headers(&[(reqwest::header::ETAG,"\"abc\""),(reqwest::header::LAST_MODIFIED,"Wed, 21 Oct 2015 07:28:00 GMT")])).unwrap();
+		assert_eq!(e.etag.as_deref(),Some("\"abc\""));
+		assert_eq!(e.last_modified.as_deref(),Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+		assert!(e.expires<=chrono::Utc::now().timestamp_millis());
+	}
+	#[test]
+	fn not_cacheable(){
+		assert!(CacheEntry::from_headers(1,&headers(&[(reqwest::header::CACHE_CONTROL,"no-store")])).is_none());
+		assert!(CacheEntry::from_headers(1,&headers(&[(reqwest::header::ETAG,"\"abc\""),(reqwest::header::CACHE_CONTROL,"no-store, max-age=60")])).is_none());
+		//期限も再検証の手段も無い
+		assert!(CacheEntry::from_headers(1,&HeaderMap::new()).is_none());
+	}
+	#[test]
+	fn rebuild_from_files(){
+		let dir=std::env::temp_dir().join(format!("disk_cache_test_{}",std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let key=DiskCache::key("https://example.com/a.png");
+		std::fs::write(dir.join(&key),[0u8;42]).unwrap();
+		std::fs::write(dir.join(INDEX_TMP_FILE),b"{").unwrap();
+		let index=CacheIndex::rebuild(&dir);
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(index.entries.len(),1);
+		assert_eq!(index.entries[&key].size,42);
+		//期限は切れていて次に使う時に取り直す
+		assert_eq!(index.entries[&key].expires,0);
+	}
+}
@@ -0,0 +1,179 @@
+use std::{path::PathBuf, sync::{atomic::{AtomicBool, AtomicU64}, Arc}};
+
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+
+use crate::ConfigFile;
+
+const UPLOAD_CHUNK_SIZE:usize=64*1024;
+#[derive(Clone,Debug)]
+pub enum UploadState{
+	Waiting,
+	Uploading,
+	Done(DriveFileRef),
+	Failed(String),
+}
+#[derive(Clone,Debug)]
+pub struct DriveFileRef{
+	pub id:String,
+	is_sensitive:bool,
+	comment:String,
+}
+#[derive(Debug)]
+pub struct UploadFile{
+	pub name:String,
+	path:PathBuf,
+	size:AtomicU64,
+	uploaded:AtomicU64,
+	pub is_sensitive:AtomicBool,
+	pub comment:std::sync::Mutex<String>,
+	state:std::sync::Mutex<UploadState>,
+}
+impl UploadFile{
+	pub fn new(path:PathBuf)->Self{
+		let name=path.file_name().map(|s|s.to_string_lossy().to_string()).unwrap_or_else(||"file".to_owned());
+		Self{
+			name,
+			path,
+			size:AtomicU64::new(0),
+			uploaded:AtomicU64::new(0),
+			is_sensitive:AtomicBool::new(false),
+			comment:std::sync::Mutex::new(String::new()),
+			state:std::sync::Mutex::new(UploadState::Waiting),
+		}
+	}
+	pub fn state(&self)->UploadState{
+		self.state.lock().unwrap().clone()
+	}
+	fn set_state(&self,state:UploadState){
+		*self.state.lock().unwrap()=state;
+	}
+	pub fn progress(&self)->f32{
+		let size=self.size.load(std::sync::atomic::Ordering::Relaxed);
+		if size==0{
+			return 0f32;
+		}
+		self.uploaded.load(std::sync::atomic::Ordering::Relaxed) as f32/size as f32
+	}
+	fn comment(&self)->String{
+		self.comment.lock().unwrap().clone()
+	}
+}
+pub(crate) async fn drive(mut recv:Receiver<Arc<UploadFile>>,ctx:egui::Context,client:Client,config:tokio::sync::watch::Receiver<Arc<ConfigFile>>){
+	while let Some(file)=recv.recv().await{
+		let ctx=ctx.clone();
+		let client=client.clone();
+		//アップロード時点のアカウントを使う
+		let config=config.borrow().clone();
+		tokio::runtime::Handle::current().spawn(async move{
+			file.set_state(UploadState::Uploading);
+			ctx.request_repaint();
+			match upload(&client,&config,&file,&ctx).await{
+				Ok(f)=>{
+					println!("drive upload {} {}",file.name,f.id);
+					file.set_state(UploadState::Done(f));
+				},
+				Err(e)=>{
+					eprintln!("drive upload {} {}",file.name,e);
+					file.set_state(UploadState::Failed(e));
+				},
+			}
+			ctx.request_repaint();
+		});
+	}
+}
+async fn upload(client:&Client,config:&ConfigFile,file:&Arc<UploadFile>,ctx:&egui::Context)->Result<DriveFileRef,String>{
+	let buf=tokio::fs::read(&file.path).await.map_err(|e|e.to_string())?;
+	let size=buf.len() as u64;
+	file.size.store(size,std::sync::atomic::Ordering::Relaxed);
+	file.uploaded.store(0,std::sync::atomic::Ordering::Relaxed);
+	let mime=image::guess_format(&buf).map(|f|f.to_mime_type()).unwrap_or("application/octet-stream");
+	let is_sensitive=file.is_sensitive.load(std::sync::atomic::Ordering::Relaxed);
+	let comment=file.comment();
+	//送信済みバイト数を進捗として記録する
+	let chunks:Vec<Vec<u8>>=buf.chunks(UPLOAD_CHUNK_SIZE).map(|c|c.to_vec()).collect();
+	let progress=file.clone();
+	let ctx=ctx.clone();
+	let stream=futures::stream::iter(chunks).map(move|chunk|{
+		progress.uploaded.fetch_add(chunk.len() as u64,std::sync::atomic::Ordering::Relaxed);
+		ctx.request_repaint();
+		Ok::<_,std::io::Error>(chunk)
+	});
+	let part=reqwest::multipart::Part::stream_with_length(reqwest::Body::wrap_stream(stream),size);
+	let part=part.file_name(file.name.clone()).mime_str(mime).map_err(|e|e.to_string())?;
+	let form=reqwest::multipart::Form::new();
+	let form=form.text("i",config.token.as_ref().unwrap().clone());
+	let form=form.text("name",file.name.clone());
+	let form=form.text("isSensitive",is_sensitive.to_string());
+	let form=if comment.is_empty(){
+		form
+	}else{
+		form.text("comment",comment.clone())
+	};
+	let form=form.part("file",part);
+	let build=client.post(format!("{}/api/drive/files/create",config.instance.as_ref().unwrap()));
+	let build=build.multipart(form);
+	let res=build.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()));
+	}
+	file.uploaded.store(size,std::sync::atomic::Ordering::Relaxed);
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	#[derive(Deserialize,Debug)]
+	struct DriveFileResponse{
+		id:String,
+	}
+	let res:DriveFileResponse=serde_json::from_slice(&res).map_err(|e|e.to_string())?;
+	Ok(DriveFileRef{
+		id:res.id,
+		is_sensitive,
+		comment,
+	})
+}
+//アップロード後にセンシティブ設定やキャプションが変更されていれば反映する
+pub(crate) async fn update_file(client:&Client,config:&ConfigFile,file:&UploadFile)->Result<String,String>{
+	let uploaded=match file.state(){
+		UploadState::Done(f)=>f,
+		UploadState::Failed(e)=>return Err(e),
+		_=>return Err(format!("{} is not uploaded",file.name)),
+	};
+	let is_sensitive=file.is_sensitive.load(std::sync::atomic::Ordering::Relaxed);
+	let comment=file.comment();
+	if uploaded.is_sensitive==is_sensitive&&uploaded.comment==comment{
+		return Ok(uploaded.id);
+	}
+	#[derive(Serialize,Debug)]
+	struct DriveFileUpdatePayload{
+		#[serde(rename = "fileId")]
+		file_id:String,
+		#[serde(rename = "isSensitive")]
+		is_sensitive:bool,
+		comment:Option<String>,
+		i:String,
+	}
+	let payload=DriveFileUpdatePayload{
+		file_id:uploaded.id.clone(),
+		is_sensitive,
+		comment:if comment.is_empty(){
+			None
+		}else{
+			Some(comment.clone())
+		},
+		i:config.token.as_ref().unwrap().clone(),
+	};
+	let build=client.post(format!("{}/api/drive/files/update",config.instance.as_ref().unwrap()));
+	let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+	let build=build.body(serde_json::to_string(&payload).map_err(|e|e.to_string())?);
+	let res=build.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()));
+	}
+	file.set_state(UploadState::Done(DriveFileRef{
+		id:uploaded.id.clone(),
+		is_sensitive,
+		comment,
+	}));
+	Ok(uploaded.id)
+}
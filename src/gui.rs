@@ -1,3 +1,14 @@
 pub(crate) mod main_ui;
 mod utils;
 mod tl;
+mod composer;
+mod notifications;
+mod thread;
+mod search;
+mod profile;
+mod relation;
+mod reaction_detail;
+mod note_capture;
+mod login;
+mod accounts;
+mod deck;
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use egui::Widget;
+
+use crate::{data_model::{DelayAssets, UrlImage}, disk_cache, image_decode::ImageSize, Account, StateFile};
+
+use super::main_ui::{LoaderReceivers, MainUI};
+
+impl <F> MainUI<F>{
+	pub(super) fn switch_account(&mut self,account:Account){
+		let loader_running=self.config.1.token.is_some()&&self.config.1.instance.is_some();
+		let config=Arc::new(self.config.1.login(account));
+		if let Err(e)=config.write(&self.config.0){
+			eprintln!("{:?}",e);
+		}
+		self.config.1=config.clone();
+		//ローダーは新しい設定で作り直される
+		let _=self.config_watch.send(config.clone());
+		self.notes.clear();
+		self.notifications.clear();
+		self.unread_notifications=0;
+		self.thread=None;
+		self.view_thread.lock().unwrap().take();
+		self.search=None;
+		self.user_detail=None;
+		self.relation.lock().unwrap().take();
+		self.follow_requests=None;
+		self.reaction_detail.lock().unwrap().take();
+		self.timeline_picker=None;
+		self.emojis=None;
+		self.meta=None;
+		self.reaction_table.clear();
+		self.stream_state=Default::default();
+		self.login=None;
+		//動いていたローダーは止まった後に新しいチャンネルを渡してくるので、それまでは何も受け取らない
+		//ログイン待ちのローダーはそのままのチャンネルで動き出す
+		if loader_running{
+			self.replace_receivers(LoaderReceivers::closed());
+		}
+		//新しいローダーで開き直す
+		self.deck=None;
+		self.deck_queue.clear();
+		self.state=StateFile::load_account(config.account_key());
+		disk_cache::disk_cache().set_max_size_mb(self.state.cache_size_mb.unwrap_or(disk_cache::DEFAULT_CACHE_SIZE_MB));
+		self.state.write(&self.delay_assets);
+		if !self.state.deck_mode{
+			self.open_timeline.lock().unwrap().replace((Some(self.state.timeline.clone()),self.state.until_id.clone()));
+		}
+	}
+	pub(super) fn recv_loader_receivers(&mut self){
+		while let Ok(receivers)=self.loader_receivers.try_recv(){
+			self.replace_receivers(receivers);
+		}
+	}
+	//前のアカウント宛てに届いていたものはチャンネルごと捨てる
+	fn replace_receivers(&mut self,receivers:LoaderReceivers){
+		self.rcv=receivers.rcv;
+		self.emojis_recv=receivers.emojis_recv;
+		self.meta_recv=receivers.meta_recv;
+		self.notifications_recv=receivers.notifications_recv;
+		self.thread_recv=receivers.thread_recv;
+		self.search_recv=receivers.search_recv;
+		self.user_detail_recv=receivers.user_detail_recv;
+		self.reaction_users_recv=receivers.reaction_users_recv;
+		self.note_update_recv=receivers.note_update_recv;
+		self.stream_state_recv=receivers.stream_state_recv;
+		self.deck_recv=receivers.deck_recv;
+		self.account_recv=receivers.account_recv;
+	}
+	//ローダーが問い合わせたユーザー情報を設定に保存する
+	pub(super) fn recv_account(&mut self){
+		while let Ok(account)=self.account_recv.try_recv(){
+			let config=Arc::new(self.config.1.update_account(account));
+			if let Err(e)=config.write(&self.config.0){
+				eprintln!("{:?}",e);
+			}
+			//トークンではなくユーザーIDで保存するので今の状態を引き継ぐ
+			let key=config.account_key();
+			if self.state.account!=key{
+				self.state.account=key;
+				self.state.write(&self.delay_assets);
+			}
+			//設定が変わってもローダーは作り直さない
+			self.config.1=config;
+		}
+	}
+	fn account_icon(&mut self,account:&Account)->Option<Arc<UrlImage>>{
+		let url=account.avatar_url.as_ref()?;
+		if let Some(icon)=self.account_icons.get(url){
+			return Some(icon.clone());
+		}
+		let icon:Arc<UrlImage>=Arc::new((url.clone(),ImageSize::Icon).into());
+		let _=self.delay_assets.try_send(DelayAssets::Image(icon.clone()));
+		self.account_icons.insert(url.clone(),icon.clone());
+		Some(icon)
+	}
+	pub(super) fn account_switcher(&mut self,ui:&mut egui::Ui){
+		let current=match self.config.1.current_account(){
+			Some(current)=>current,
+			None=>return,
+		};
+		let accounts:Vec<_>=self.config.1.accounts().into_iter().map(|a|{
+			let icon=self.account_icon(&a);
+			(a,icon)
+		}).collect();
+		let mut switch=None;
+		let mut add=false;
+		ui.menu_button(current.label(),|ui|{
+			for (account,icon) in accounts{
+				let label=account.label();
+				let bt=match icon{
+					Some(icon)=>egui::Button::image_and_text(self.get_image(&icon).max_size([16f32,16f32].into()),label),
+					None=>egui::Button::new(label),
+				};
+				if bt.selected(account.is_same(&current)).ui(ui).clicked(){
+					if !account.is_same(&current){
+						switch=Some(account);
+					}
+					ui.close_menu();
+				}
+			}
+			ui.separator();
+			if ui.button(&self.locale.add_account).clicked(){
+				add=true;
+				ui.close_menu();
+			}
+		});
+		if let Some(account)=switch{
+			self.switch_account(account);
+		}
+		if add{
+			self.login=Some(Default::default());
+		}
+	}
+}
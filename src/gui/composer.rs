@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use egui::{Color32, Widget};
+
+use crate::{data_model::{Note, Visibility}, drive::{UploadFile, UploadState}};
+
+use super::main_ui::MainUI;
+
+const DEFAULT_MAX_NOTE_TEXT_LENGTH:u64=3000;
+#[derive(Default)]
+pub(super) struct Composer{
+	pub(super) text:String,
+	pub(super) cw:Option<String>,
+	pub(super) visibility:Visibility,
+	pub(super) visible_users:String,
+	pub(super) local_only:bool,
+	pub(super) reply:Option<Arc<Note>>,
+	pub(super) quote:Option<Arc<Note>>,
+	pub(super) files:Vec<Arc<UploadFile>>,
+	pub(super) file_path:String,
+	//送信に失敗した理由。内容は残したまま表示する
+	error:Option<String>,
+}
+impl Composer{
+	pub(super) fn set_reply(&mut self,note:&Arc<Note>){
+		let note=target_note(note);
+		//返信先より広い公開範囲では投稿しない
+		fn rank(v:&Visibility)->u8{
+			match v{
+				Visibility::Public => 0,
+				Visibility::Home => 1,
+				Visibility::Followers => 2,
+				Visibility::Specified => 3,
+			}
+		}
+		if rank(&note.visibility)>rank(&self.visibility){
+			self.visibility=note.visibility;
+		}
+		self.reply=Some(note);
+	}
+	pub(super) fn set_quote(&mut self,note:&Arc<Note>){
+		self.quote=Some(target_note(note));
+	}
+	pub(super) fn clear(&mut self){
+		let visibility=self.visibility;
+		let local_only=self.local_only;
+		*self=Self::default();
+		self.visibility=visibility;
+		self.local_only=local_only;
+	}
+	pub(super) fn text_length(&self)->u64{
+		self.text.chars().count() as u64
+	}
+	fn can_send(&self,max:u64)->bool{
+		let len=self.text_length();
+		if len>max{
+			return false;
+		}
+		//アップロードが終わるまで待つ
+		for f in &self.files{
+			if let UploadState::Done(_)=f.state(){
+				continue;
+			}
+			return false;
+		}
+		//引用やファイルのみの場合本文は空でも良い
+		len>0||self.quote.is_some()||!self.files.is_empty()
+	}
+}
+pub(super) fn target_note(note:&Arc<Note>)->Arc<Note>{
+	//単純なリノートはリノート元を対象にする
+	if note.is_simple_renote(){
+		if let Some(q)=note.quote.as_ref(){
+			return q.clone();
+		}
+	}
+	note.clone()
+}
+impl <F> MainUI<F>{
+	pub(super) fn visibility_label(&self,v:&Visibility)->&str{
+		match v{
+			Visibility::Public => &self.locale.visibility_public,
+			Visibility::Home => &self.locale.visibility_home,
+			Visibility::Followers => &self.locale.visibility_followers,
+			Visibility::Specified => &self.locale.visibility_specified,
+		}
+	}
+	fn composer_target(&self,ui:&mut egui::Ui,label:&str,note:&Note)->bool{
+		let mut cancel=false;
+		ui.horizontal_wrapped(|ui|{
+			ui.colored_label(Color32::from_gray(100),label);
+			note.user.display_name.render(ui,true,&self.dummy,self.animate_frame);
+			let len=note.text.raw.char_indices().nth(30).map(|(v,_)|v).unwrap_or(note.text.raw.len());
+			ui.label(&note.text.raw[..len]);
+			if ui.button(&self.locale.cancel).clicked(){
+				cancel=true;
+			}
+		});
+		cancel
+	}
+	fn attach_file(&self,composer:&mut Composer,path:std::path::PathBuf){
+		let file=Arc::new(UploadFile::new(path));
+		if self.drive.blocking_send(file.clone()).is_ok(){
+			composer.files.push(file);
+		}
+	}
+	fn composer_files(&self,ui:&mut egui::Ui,composer:&mut Composer){
+		let dropped=ui.ctx().input(|i|i.raw.dropped_files.clone());
+		for f in dropped{
+			if let Some(path)=f.path{
+				self.attach_file(composer,path);
+			}
+		}
+		let mut remove=None;
+		for (idx,file) in composer.files.iter().enumerate(){
+			ui.horizontal_wrapped(|ui|{
+				ui.label(&file.name);
+				match file.state(){
+					UploadState::Failed(e)=>{
+						ui.colored_label(Color32::from_rgb(255,0,0),e);
+					},
+					UploadState::Done(_)=>{},
+					_=>{
+						egui::ProgressBar::new(file.progress()).desired_width(80f32).show_percentage().ui(ui);
+					}
+				}
+				let mut is_sensitive=file.is_sensitive.load(std::sync::atomic::Ordering::Relaxed);
+				if ui.checkbox(&mut is_sensitive,&self.locale.sensitive).changed(){
+					file.is_sensitive.store(is_sensitive,std::sync::atomic::Ordering::Relaxed);
+				}
+				if ui.button(&self.locale.remove).clicked(){
+					remove=Some(idx);
+				}
+			});
+			let mut comment=file.comment.lock().unwrap();
+			egui::TextEdit::singleline(&mut *comment).id_source(("composer_file_comment",idx)).hint_text(&self.locale.file_comment).desired_width(f32::INFINITY).ui(ui);
+		}
+		if let Some(idx)=remove{
+			composer.files.remove(idx);
+		}
+		ui.horizontal(|ui|{
+			let bt=ui.button(&self.locale.attach_file);
+			egui::TextEdit::singleline(&mut composer.file_path).hint_text(&self.locale.file_path).desired_width(f32::INFINITY).ui(ui);
+			if bt.clicked()&&!composer.file_path.is_empty(){
+				let path=std::path::PathBuf::from(std::mem::take(&mut composer.file_path));
+				self.attach_file(composer,path);
+			}
+		});
+	}
+	pub(super) fn composer(&self,ui:&mut egui::Ui){
+		let mut composer=self.composer.lock().unwrap();
+		if let Some(reply)=composer.reply.clone(){
+			if self.composer_target(ui,&self.locale.reply_to,&reply){
+				composer.reply=None;
+			}
+		}
+		if let Some(quote)=composer.quote.clone(){
+			if self.composer_target(ui,&self.locale.quote_to,&quote){
+				composer.quote=None;
+			}
+		}
+		let mut cw_enabled=composer.cw.is_some();
+		if ui.checkbox(&mut cw_enabled,&self.locale.cw_enabled).changed(){
+			composer.cw=if cw_enabled{
+				Some(String::new())
+			}else{
+				None
+			};
+		}
+		if let Some(cw)=composer.cw.as_mut(){
+			egui::TextEdit::singleline(cw).hint_text(&self.locale.cw_placeholder).desired_width(f32::INFINITY).ui(ui);
+		}
+		egui::TextEdit::multiline(&mut composer.text).hint_text(&self.locale.note_placeholder).desired_rows(3).desired_width(f32::INFINITY).ui(ui);
+		self.composer_files(ui,&mut composer);
+		if composer.visibility==Visibility::Specified{
+			egui::TextEdit::singleline(&mut composer.visible_users).hint_text(&self.locale.visible_users).desired_width(f32::INFINITY).ui(ui);
+		}
+		ui.horizontal_wrapped(|ui|{
+			let selected=self.visibility_label(&composer.visibility).to_owned();
+			egui::ComboBox::from_id_source("composer_visibility").selected_text(selected).show_ui(ui,|ui|{
+				for v in [Visibility::Public,Visibility::Home,Visibility::Followers,Visibility::Specified]{
+					let label=self.visibility_label(&v).to_owned();
+					ui.selectable_value(&mut composer.visibility,v,label);
+				}
+			});
+			ui.checkbox(&mut composer.local_only,&self.locale.local_only);
+			let max=self.meta.as_ref().map(|m|m.max_note_text_length).unwrap_or_default().unwrap_or(DEFAULT_MAX_NOTE_TEXT_LENGTH);
+			let remaining=max as i64-composer.text_length() as i64;
+			ui.with_layout(egui::Layout::right_to_left(egui::Align::Center),|ui|{
+				let bt=egui::Button::new(&self.locale.note_post);
+				if ui.add_enabled(composer.can_send(max),bt).clicked(){
+					match self.note_send(&composer){
+						Ok(())=>composer.clear(),
+						Err(e)=>composer.error=Some(e),
+					}
+				}
+				let counter=egui::RichText::new(remaining.to_string());
+				let counter=if remaining<0{
+					counter.color(Color32::from_rgb(255,0,0))
+				}else{
+					counter
+				};
+				ui.label(counter);
+			});
+		});
+		if let Some(e)=composer.error.as_ref(){
+			ui.colored_label(Color32::from_rgb(255,0,0),e);
+		}
+	}
+}
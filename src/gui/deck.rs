@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use egui::{ScrollArea, Widget};
+
+use crate::{data_model::Note, load_misskey::{self, LoadSrc, TimeLine}};
+
+use super::main_ui::MainUI;
+
+const COLUMN_WIDTH:f32=360f32;
+pub(super) struct DeckColumn{
+	//ローダー側のチャンネルと対応付けるID
+	id:u32,
+	tl:TimeLine,
+	notes:Vec<Arc<Note>>,
+}
+enum ColumnAction{
+	Reload,
+	Old(String),
+	Close,
+}
+impl <F> MainUI<F>{
+	fn send_columns(&mut self,src:Vec<LoadSrc>){
+		self.deck_queue.extend(src);
+	}
+	//UIを止めないように空きがある分だけ順に送る
+	fn flush_deck_queue(&mut self,ctx:&egui::Context){
+		while let Some(src)=self.deck_queue.pop_front(){
+			match self.reload.try_send(src){
+				Ok(())=>{},
+				Err(tokio::sync::mpsc::error::TrySendError::Full(src))=>{
+					self.deck_queue.push_front(src);
+					ctx.request_repaint_after(std::time::Duration::from_millis(100));
+					break;
+				},
+				Err(tokio::sync::mpsc::error::TrySendError::Closed(_))=>{
+					self.deck_queue.clear();
+					break;
+				},
+			}
+		}
+	}
+	fn new_column(&mut self,tl:TimeLine)->DeckColumn{
+		self.deck_id+=1;
+		DeckColumn{
+			id:self.deck_id,
+			tl,
+			notes:vec![],
+		}
+	}
+	fn column_src(&self,column:&DeckColumn,until_id:Option<String>)->LoadSrc{
+		LoadSrc::Column(column.id,load_misskey::TLOption{
+			until_id,
+			since_id:None,
+			limit:self.state.page_notes,
+			tl:column.tl.clone(),
+			known_notes:column.notes.clone(),
+			websocket:true,
+		})
+	}
+	//保存された列を開く
+	pub(super) fn open_deck(&mut self){
+		let deck:Vec<_>=self.state.deck.clone().into_iter().map(|tl|self.new_column(tl)).collect();
+		let src=deck.iter().map(|column|self.column_src(column,None)).collect();
+		self.send_columns(src);
+		self.deck=Some(deck);
+	}
+	pub(super) fn close_deck(&mut self){
+		if let Some(deck)=self.deck.take(){
+			for column in &deck{
+				for n in &column.notes{
+					self.capture_note(n,false);
+				}
+			}
+			self.send_columns(deck.iter().map(|column|LoadSrc::CloseColumn(column.id)).collect());
+		}
+	}
+	pub(super) fn add_column(&mut self,tl:TimeLine){
+		if self.state.deck.contains(&tl){
+			return;
+		}
+		//開く前なら保存だけしてopen_deckに任せる
+		if self.deck.is_some(){
+			let column=self.new_column(tl.clone());
+			self.send_columns(vec![self.column_src(&column,None)]);
+			self.deck.as_mut().unwrap().push(column);
+		}
+		self.state.deck.push(tl);
+		self.state.write(&self.delay_assets);
+	}
+	fn clear_column(&self,column:&mut DeckColumn){
+		for n in &column.notes{
+			self.capture_note(n,false);
+		}
+		column.notes.clear();
+	}
+	pub(super) fn recv_deck(&mut self,ctx:&egui::Context){
+		self.flush_deck_queue(ctx);
+		while let Ok((id,n))=self.deck_recv.try_recv(){
+			let mut deck=match self.deck.take(){
+				Some(deck)=>deck,
+				None=>continue,
+			};
+			//閉じた列宛てのノートは捨てる
+			if let Some(column)=deck.iter_mut().find(|column|column.id==id){
+				//blurhashは即座に読み込む
+				tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async{
+					for f in n.files.iter().chain(n.quote.iter().flat_map(|q|q.files.iter())){
+						if let Some(blurhash)=&f.blurhash{
+							blurhash.load_gpu(ctx,&self.config.1).await;
+						}
+					}
+				});
+				match column.notes.iter().position(|old|old.id==n.id){
+					Some(idx)=>column.notes[idx]=n,
+					None=>{
+						self.capture_note(&n,true);
+						column.notes.push(n);
+					},
+				}
+				if column.notes.len()>self.state.page_notes as usize{
+					let n=column.notes.remove(0);
+					self.capture_note(&n,false);
+				}
+			}
+			self.deck=Some(deck);
+			ctx.request_repaint();
+		}
+	}
+	pub(super) fn update_deck_note(&mut self,n:&Arc<Note>){
+		for column in self.deck.iter_mut().flatten(){
+			for old in column.notes.iter_mut(){
+				if old.id==n.id{
+					*old=n.clone();
+				}
+			}
+		}
+	}
+	pub(super) fn remove_deck_note(&mut self,id:&str){
+		let mut removed=vec![];
+		for column in self.deck.iter_mut().flatten(){
+			column.notes.retain(|n|{
+				if n.id==id{
+					removed.push(n.clone());
+					false
+				}else{
+					true
+				}
+			});
+		}
+		for n in removed{
+			self.capture_note(&n,false);
+		}
+	}
+	fn column_label(&self,column:&DeckColumn)->String{
+		let named=|items:Option<&Vec<super::utils::NamedItem>>,id:&String,kind:&str|{
+			items.and_then(|items|items.iter().find(|item|&item.id==id)).map(|item|item.name.clone()).unwrap_or_else(||format!("{} {}",kind,id))
+		};
+		match &column.tl{
+			TimeLine::Home=>"HTL".to_owned(),
+			TimeLine::Local=>"LTL".to_owned(),
+			TimeLine::Social=>"STL".to_owned(),
+			TimeLine::Global=>"GTL".to_owned(),
+			TimeLine::List(id)=>named(self.timeline_picker.as_ref().map(|(lists,_)|lists),id,&self.locale.lists),
+			TimeLine::Antenna(id)=>named(self.timeline_picker.as_ref().map(|(_,antennas)|antennas),id,&self.locale.antennas),
+			TimeLine::Hashtag(tag)=>format!("#{}",tag),
+			TimeLine::User(id)=>{
+				column.notes.iter().map(|n|&n.user).find(|u|&u.id==id).map(|u|format!("@{}",u.username)).unwrap_or_else(||id.clone())
+			},
+		}
+	}
+	pub(super) fn deck_view(&mut self,ui:&mut egui::Ui){
+		let mut deck=match self.deck.take(){
+			Some(deck)=>deck,
+			None=>return,
+		};
+		if deck.is_empty(){
+			ui.label(&self.locale.deck_empty);
+		}
+		let mut actions=vec![];
+		ScrollArea::horizontal().id_source("deck").show(ui,|ui|{
+			ui.horizontal_top(|ui|{
+				for column in &deck{
+					ui.vertical(|ui|{
+						ui.set_width(COLUMN_WIDTH);
+						ui.horizontal(|ui|{
+							ui.strong(self.column_label(column));
+							if ui.button(&self.locale.reload).clicked(){
+								actions.push((column.id,ColumnAction::Reload));
+							}
+							if ui.button(&self.locale.close).clicked(){
+								actions.push((column.id,ColumnAction::Close));
+							}
+						});
+						//列ごとにスクロール位置を持つ
+						ScrollArea::vertical().id_source(("deck_column",column.id)).show_viewport(ui,|ui,rect|{
+							ui.set_width(COLUMN_WIDTH);
+							let mut y=0f32;
+							for note in column.notes.iter().rev(){
+								self.viewport_note(ui,rect,&mut y,note,|ui|self.note_ui(ui,note));
+							}
+							if let Some(n)=column.notes.first(){
+								if egui::Button::new(&self.locale.load_old_timeline).ui(ui).clicked(){
+									actions.push((column.id,ColumnAction::Old(n.id.clone())));
+								}
+							}
+						});
+					});
+					ui.separator();
+				}
+			});
+		});
+		for (id,action) in actions{
+			let idx=match deck.iter().position(|column|column.id==id){
+				Some(idx)=>idx,
+				None=>continue,
+			};
+			match action{
+				ColumnAction::Reload=>{
+					self.clear_column(&mut deck[idx]);
+					self.send_columns(vec![self.column_src(&deck[idx],None)]);
+				},
+				ColumnAction::Old(until_id)=>{
+					//古いページを表示している間はストリームを止める
+					//IDを振り直して受信途中のストリームのノートを捨てる
+					self.clear_column(&mut deck[idx]);
+					let old_id=deck[idx].id;
+					self.deck_id+=1;
+					deck[idx].id=self.deck_id;
+					self.send_columns(vec![LoadSrc::CloseColumn(old_id),self.column_src(&deck[idx],Some(until_id))]);
+				},
+				ColumnAction::Close=>{
+					let mut column=deck.remove(idx);
+					self.clear_column(&mut column);
+					self.send_columns(vec![LoadSrc::CloseColumn(column.id)]);
+					self.state.deck.retain(|tl|tl!=&column.tl);
+					self.state.write(&self.delay_assets);
+				},
+			}
+		}
+		self.deck=Some(deck);
+	}
+}
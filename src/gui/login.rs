@@ -0,0 +1,105 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use egui::{Color32, Widget};
+
+use crate::{miauth::{self, MiAuthSession}, Account};
+
+use super::main_ui::MainUI;
+
+#[derive(Default)]
+pub(super) struct LoginView{
+	instance:String,
+	//認可ページのURLと中断フラグ
+	waiting:Option<(String,Arc<AtomicBool>)>,
+	result:Arc<std::sync::Mutex<Option<Result<Account,String>>>>,
+	error:Option<String>,
+}
+impl LoginView{
+	fn start(&mut self,ctx:&egui::Context,client:reqwest::Client,instance:String){
+		let session=MiAuthSession::new(instance);
+		//ブラウザからのコールバックを受けるポート
+		let listener=std::net::TcpListener::bind("127.0.0.1:0").and_then(|l|{
+			l.set_nonblocking(true)?;
+			Ok(l)
+		}).map_err(|e|eprintln!("{:?}",e)).ok();
+		let callback=listener.as_ref().and_then(|l|l.local_addr().ok()).map(|addr|format!("http://127.0.0.1:{}/callback",addr.port()));
+		let url=session.url(callback.as_deref());
+		ctx.open_url(egui::OpenUrl::new_tab(&url));
+		let cancel=Arc::new(AtomicBool::new(false));
+		self.waiting=Some((url,cancel.clone()));
+		self.error=None;
+		let result=self.result.clone();
+		let ctx=ctx.clone();
+		std::thread::spawn(move||{
+			tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+				let listener=listener.and_then(|l|tokio::net::TcpListener::from_std(l).ok());
+				let res=session.wait_token(&client,listener,cancel.clone()).await;
+				if !cancel.load(std::sync::atomic::Ordering::Relaxed){
+					*result.lock().unwrap()=Some(res);
+					ctx.request_repaint();
+				}
+			});
+		});
+	}
+}
+impl <F> MainUI<F>{
+	pub(super) fn login(&mut self,ui:&mut egui::Ui){
+		let mut view=match self.login.take(){
+			Some(view)=>view,
+			None=>LoginView{
+				instance:self.config.1.instance.clone().unwrap_or_default(),
+				..Default::default()
+			},
+		};
+		let res=view.result.lock().unwrap().take();
+		match res{
+			Some(Ok(account))=>{
+				self.switch_account(account);
+				ui.ctx().request_repaint();
+				return;
+			},
+			Some(Err(e))=>{
+				view.waiting=None;
+				view.error=Some(format!("{} {}",self.locale.login_failed,e));
+			},
+			None=>{},
+		}
+		ui.heading(&self.locale.login);
+		let mut close=false;
+		if let Some((url,cancel))=view.waiting.as_ref(){
+			ui.horizontal_wrapped(|ui|{
+				ui.label(&self.locale.login_waiting);
+				egui::ProgressBar::new(0f32).desired_width(10f32).animate(true).ui(ui);
+			});
+			let canceled=ui.horizontal_wrapped(|ui|{
+				ui.hyperlink_to(&self.locale.login_open_browser,url);
+				ui.button(&self.locale.cancel).clicked()
+			}).inner;
+			if canceled{
+				cancel.store(true,std::sync::atomic::Ordering::Relaxed);
+				view.waiting=None;
+			}
+		}else{
+			ui.horizontal_wrapped(|ui|{
+				let res=egui::TextEdit::singleline(&mut view.instance).hint_text(&self.locale.login_instance).ui(ui);
+				let enter=res.lost_focus()&&ui.input(|i|i.key_pressed(egui::Key::Enter));
+				if ui.button(&self.locale.login).clicked()||enter{
+					match miauth::normalize_instance(&view.instance){
+						Some(instance)=>view.start(ui.ctx(),self.client.clone(),instance),
+						None=>view.error=Some(self.locale.login_invalid_instance.clone()),
+					}
+				}
+				//アカウント追加を止める
+				if self.config.1.token.is_some()&&ui.button(&self.locale.cancel).clicked(){
+					close=true;
+				}
+			});
+		}
+		if let Some(e)=view.error.as_ref(){
+			ui.colored_label(Color32::from_rgb(255,0,0),e);
+		}
+		if !close{
+			self.login=Some(view);
+		}
+	}
+}
@@ -1,5 +1,5 @@
 
-use std::{io::Read, sync::Arc};
+use std::{collections::HashMap, io::Read, sync::Arc};
 
 use eframe::{egui, NativeOptions};
 
@@ -8,9 +8,9 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Receiver;
 
-use crate::{data_model::{self, Visibility}, delay_assets, load_misskey, ConfigFile, LocaleFile, StateFile};
+use crate::{data_model::{self, Visibility}, delay_assets, disk_cache, drive, load_misskey, ConfigFile, LocaleFile, StateFile};
 
-use super::utils::ZoomMediaView;
+use super::{composer::Composer, deck::DeckColumn, login::LoginView, reaction_detail::ReactionDetail, relation::{FollowRequest, UserRelation}, search::SearchView, utils::{NamedItem, ZoomMediaView}};
 
 pub(crate) fn open<F>(options:NativeOptions,ime_show:F)where F:FnMut(&mut bool)+'static{
 	/*
@@ -27,17 +27,84 @@ pub(crate) fn open<F>(options:NativeOptions,ime_show:F)where F:FnMut(&mut bool)+
 	let config=crate::load_config();
 	let locale=crate::load_locale();
 	let (assets,assets_recv)=tokio::sync::mpsc::channel(10);
-	let (note_ui,recv)=tokio::sync::mpsc::channel(4);
 	let (reload,reload_recv)=tokio::sync::mpsc::channel(1);
-	let (emojis_send,emojis_recv)=tokio::sync::mpsc::channel(1);
-	let config0=config.1.clone();
+	let (drive_send,drive_recv)=tokio::sync::mpsc::channel(4);
+	let (capture,capture_recv)=tokio::sync::mpsc::channel(256);
+	//ローダーを作り直す度に受信側を受け取る
+	let (loader_receivers_send,loader_receivers)=std::sync::mpsc::channel();
+	//ログインやアカウント切り替えで新しい設定が届く
+	let (config_watch,config_recv)=tokio::sync::watch::channel(config.1.clone());
+	let mut config_recv0=config_recv.clone();
 	let client=Client::new();
 	let client0=client.clone();
 	let assets0=assets.clone();
 	std::thread::spawn(move||{
-		let rt=tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
-		rt.block_on(load_misskey::load_misskey(config0,note_ui,assets0,client0,reload_recv,emojis_send))
+		let reload_recv=Arc::new(tokio::sync::Mutex::new(reload_recv));
+		let capture_recv=Arc::new(tokio::sync::Mutex::new(capture_recv));
+		loop{
+			//前のアカウント宛てのものが混ざらないようにチャンネルも作り直す
+			let (note_ui,rcv)=tokio::sync::mpsc::channel(4);
+			let (emojis_send,emojis_recv)=tokio::sync::mpsc::channel(1);
+			let (meta_send,meta_recv)=tokio::sync::mpsc::channel(1);
+			let (notification_send,notifications_recv)=tokio::sync::mpsc::channel(8);
+			let (thread_send,thread_recv)=tokio::sync::mpsc::channel(1);
+			let (search_send,search_recv)=tokio::sync::mpsc::channel(1);
+			let (user_detail_send,user_detail_recv)=tokio::sync::mpsc::channel(1);
+			let (reaction_users_send,reaction_users_recv)=tokio::sync::mpsc::channel(1);
+			let (note_update_send,note_update_recv)=tokio::sync::mpsc::channel(8);
+			let (stream_state_send,stream_state_recv)=tokio::sync::watch::channel(Default::default());
+			let (deck_send,deck_recv)=tokio::sync::mpsc::channel(4);
+			let (account_send,account_recv)=tokio::sync::mpsc::channel(1);
+			let receivers=LoaderReceivers{
+				rcv,
+				emojis_recv,
+				meta_recv,
+				notifications_recv,
+				thread_recv,
+				search_recv,
+				user_detail_recv,
+				reaction_users_recv,
+				note_update_recv,
+				stream_state_recv,
+				deck_recv,
+				account_recv,
+			};
+			if loader_receivers_send.send(receivers).is_err(){
+				break;
+			}
+			//設定が変わる度にランタイムごと作り直す
+			let rt=tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+			let config0=match rt.block_on(crate::miauth::wait_login(&mut config_recv0)){
+				Some(config0)=>config0,
+				None=>break,
+			};
+			let changed=rt.block_on(async{
+				let loader=load_misskey::load_misskey(config0,note_ui,assets0.clone(),client0.clone(),reload_recv.clone(),emojis_send,meta_send,notification_send,thread_send,search_send,user_detail_send,reaction_users_send,capture_recv.clone(),note_update_send,stream_state_send,deck_send,account_send);
+				tokio::select!{
+					_=loader=>config_recv0.changed().await,
+					changed=config_recv0.changed()=>changed,
+				}
+			});
+			rt.shutdown_background();
+			if changed.is_err(){
+				break;
+			}
+		}
 	});
+	let LoaderReceivers{
+		rcv:recv,
+		emojis_recv,
+		meta_recv,
+		notifications_recv:notification_recv,
+		thread_recv,
+		search_recv,
+		user_detail_recv,
+		reaction_users_recv,
+		note_update_recv,
+		stream_state_recv,
+		deck_recv,
+		account_recv,
+	}=loader_receivers.recv().unwrap();
 	let dummy=data_model::UrlImage::dummy();
 	eframe::run_native(
 		"YojoArtApp",
@@ -78,13 +145,31 @@ pub(crate) fn open<F>(options:NativeOptions,ime_show:F)where F:FnMut(&mut bool)+
 				let rt=tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
 				rt.block_on(delay_assets::delay_assets(assets_recv,ctx,client0,config0));
 			});
-			let state=StateFile::load().unwrap_or_default();
-			let open_timeline=std::sync::Mutex::new(Some((Some(state.timeline.clone()),state.until_id.clone())));
+			let ctx=cc.egui_ctx.clone();
+			let client0=client.clone();
+			std::thread::spawn(||{
+				let rt=tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+				rt.block_on(async move{
+					drive::drive(drive_recv,ctx,client0,config_recv).await;
+				});
+			});
+			let state=StateFile::load_account(config.1.account_key());
+			disk_cache::disk_cache().set_max_size_mb(state.cache_size_mb.unwrap_or(disk_cache::DEFAULT_CACHE_SIZE_MB));
+			std::thread::spawn(||disk_cache::disk_cache().load_index());
+			//デッキ表示ではopen_deckで列を開く
+			let open_timeline=std::sync::Mutex::new(if state.deck_mode{
+				None
+			}else{
+				Some((Some(state.timeline.clone()),state.until_id.clone()))
+			});
 			Box::new(MainUI{
 				config,
 				locale,
-				input_text:String::new(),
+				composer:std::sync::Mutex::new(Composer::default()),
+				drive:drive_send,
 				emojis:None,
+				meta:None,
+				meta_recv,
 				reaction_table:vec![],
 				emojis_recv,
 				reaction_picker:std::sync::Mutex::new(None),
@@ -96,6 +181,7 @@ pub(crate) fn open<F>(options:NativeOptions,ime_show:F)where F:FnMut(&mut bool)+
 				animate_frame:0u64,
 				delay_assets:assets,
 				show_cw:std::sync::Mutex::new(None),
+				pending_capture:std::sync::Mutex::new(HashMap::new()),
 				reload,
 				client,
 				themify,
@@ -107,18 +193,89 @@ pub(crate) fn open<F>(options:NativeOptions,ime_show:F)where F:FnMut(&mut bool)+
 				open_timeline,
 				state,
 				rn_dialog:std::sync::Mutex::new(None),
+				timeline_picker:None,
+				notifications:vec![],
+				notifications_recv:notification_recv,
+				unread_notifications:0,
+				view_notifications:false,
+				thread:None,
+				thread_recv,
+				view_thread:std::sync::Mutex::new(None),
+				thread_error:std::sync::Mutex::new(None),
+				search:None,
+				search_recv,
+				user_detail:None,
+				user_detail_recv,
+				relation:std::sync::Mutex::new(None),
+				follow_requests:None,
+				reaction_detail:std::sync::Mutex::new(None),
+				reaction_users_recv,
+				capture,
+				note_update_recv,
+				stream_state:Default::default(),
+				stream_state_recv,
+				config_watch,
+				login:None,
+				account_icons:Default::default(),
+				deck:None,
+				deck_recv,
+				account_recv,
+				loader_receivers,
+				deck_id:0,
+				deck_queue:Default::default(),
+				debug_overlay:false,
 			})
 		}),
 	).unwrap();
 }
+//ローダーからUIへのチャンネルの受信側
+pub(super) struct LoaderReceivers{
+	pub(super) rcv:Receiver<Arc<data_model::Note>>,
+	pub(super) emojis_recv:Receiver<data_model::EmojiCache>,
+	pub(super) meta_recv:Receiver<Arc<load_misskey::ApiMeta>>,
+	pub(super) notifications_recv:Receiver<(Arc<data_model::Notification>,bool)>,
+	pub(super) thread_recv:Receiver<Result<data_model::NoteThread,(String,String)>>,
+	pub(super) search_recv:Receiver<Result<(Vec<Arc<data_model::Note>>,bool),String>>,
+	pub(super) user_detail_recv:Receiver<data_model::UserDetail>,
+	pub(super) reaction_users_recv:Receiver<data_model::ReactionUsers>,
+	pub(super) note_update_recv:Receiver<load_misskey::NoteUpdate>,
+	pub(super) stream_state_recv:tokio::sync::watch::Receiver<load_misskey::StreamState>,
+	pub(super) deck_recv:Receiver<(u32,Arc<data_model::Note>)>,
+	pub(super) account_recv:Receiver<crate::Account>,
+}
+impl LoaderReceivers{
+	//次のローダーが動き出すまで何も届かない受信側
+	pub(super) fn closed()->Self{
+		fn closed<T>()->Receiver<T>{
+			tokio::sync::mpsc::channel(1).1
+		}
+		Self{
+			rcv:closed(),
+			emojis_recv:closed(),
+			meta_recv:closed(),
+			notifications_recv:closed(),
+			thread_recv:closed(),
+			search_recv:closed(),
+			user_detail_recv:closed(),
+			reaction_users_recv:closed(),
+			note_update_recv:closed(),
+			stream_state_recv:tokio::sync::watch::channel(Default::default()).1,
+			deck_recv:closed(),
+			account_recv:closed(),
+		}
+	}
+}
 pub(super) struct MainUI<F>{
 	pub(super) config:(String, Arc<ConfigFile>),
 	pub(super) locale:Arc<LocaleFile>,
 	pub(super) emojis:Option<data_model::EmojiCache>,
 	pub(super) reaction_table:Vec<data_model::LocalEmojis>,
 	pub(super) emojis_recv:Receiver<data_model::EmojiCache>,
+	pub(super) meta:Option<Arc<load_misskey::ApiMeta>>,
+	pub(super) meta_recv:Receiver<Arc<load_misskey::ApiMeta>>,
 	pub(super) reaction_picker:std::sync::Mutex<Option<String>>,
-	pub(super) input_text:String,
+	pub(super) composer:std::sync::Mutex<Composer>,
+	pub(super) drive:tokio::sync::mpsc::Sender<Arc<drive::UploadFile>>,
 	pub(super) show_ime:bool,
 	pub(super) button_handle: Box<F>,
 	pub(super) notes:Vec<Arc<data_model::Note>>,
@@ -127,6 +284,8 @@ pub(super) struct MainUI<F>{
 	pub(super) animate_frame:u64,
 	pub(super) delay_assets:tokio::sync::mpsc::Sender<data_model::DelayAssets>,
 	pub(super) show_cw:std::sync::Mutex<Option<String>>,
+	//ローダーに送りきれていない購読の増減
+	pub(super) pending_capture:std::sync::Mutex<HashMap<String,i32>>,
 	pub(super) reload: tokio::sync::mpsc::Sender<load_misskey::LoadSrc>,
 	pub(super) client: Client,
 	pub(super) themify:egui::FontFamily,
@@ -138,9 +297,44 @@ pub(super) struct MainUI<F>{
 	pub(super) open_timeline:std::sync::Mutex<Option<(Option<load_misskey::TimeLine>,Option<String>)>>,
 	pub(super) state:StateFile,
 	pub(super) rn_dialog:std::sync::Mutex<Option<(String,Visibility)>>,
+	pub(super) timeline_picker:Option<(Vec<NamedItem>,Vec<NamedItem>)>,
+	pub(super) notifications:Vec<Arc<data_model::Notification>>,
+	pub(super) notifications_recv:Receiver<(Arc<data_model::Notification>,bool)>,
+	pub(super) unread_notifications:usize,
+	pub(super) view_notifications:bool,
+	pub(super) thread:Option<data_model::NoteThread>,
+	pub(super) thread_recv:Receiver<Result<data_model::NoteThread,(String,String)>>,
+	pub(super) view_thread:std::sync::Mutex<Option<String>>,
+	//読み込みに失敗したノートのIDと理由
+	pub(super) thread_error:std::sync::Mutex<Option<(String,String)>>,
+	pub(super) search:Option<SearchView>,
+	pub(super) search_recv:Receiver<Result<(Vec<Arc<data_model::Note>>,bool),String>>,
+	pub(super) user_detail:Option<data_model::UserDetail>,
+	pub(super) user_detail_recv:Receiver<data_model::UserDetail>,
+	pub(super) relation:std::sync::Mutex<Option<UserRelation>>,
+	pub(super) follow_requests:Option<Vec<FollowRequest>>,
+	pub(super) reaction_detail:std::sync::Mutex<Option<ReactionDetail>>,
+	pub(super) reaction_users_recv:Receiver<data_model::ReactionUsers>,
+	pub(super) capture:tokio::sync::mpsc::Sender<load_misskey::NoteCapture>,
+	pub(super) note_update_recv:Receiver<load_misskey::NoteUpdate>,
+	pub(super) stream_state:load_misskey::StreamState,
+	pub(super) stream_state_recv:tokio::sync::watch::Receiver<load_misskey::StreamState>,
+	pub(super) config_watch:tokio::sync::watch::Sender<Arc<ConfigFile>>,
+	pub(super) login:Option<LoginView>,
+	pub(super) account_icons:std::collections::HashMap<String,Arc<data_model::UrlImage>>,
+	pub(super) deck:Option<Vec<DeckColumn>>,
+	pub(super) deck_recv:Receiver<(u32,Arc<data_model::Note>)>,
+	pub(super) account_recv:Receiver<crate::Account>,
+	pub(super) loader_receivers:std::sync::mpsc::Receiver<LoaderReceivers>,
+	pub(super) deck_id:u32,
+	//ローダーへ順番に送る列の読み込みと閉じる要求
+	pub(super) deck_queue:std::collections::VecDeque<load_misskey::LoadSrc>,
+	pub(super) debug_overlay:bool,
 }
 impl <F> eframe::App for MainUI<F> where F:FnMut(&mut bool)+'static{
 	fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+		data_model::next_frame();
+		self.recv_loader_receivers();
 		if self.config.1.is_animation.unwrap_or(data_model::DEFAULT_ANIMATION){
 			ctx.request_repaint();
 			self.animate_frame=chrono::Utc::now().timestamp_millis() as u64;
@@ -161,6 +355,46 @@ impl <F> eframe::App for MainUI<F> where F:FnMut(&mut bool)+'static{
 			}
 			self.emojis=Some(emoji);
 		}
+		if let Ok(meta)=self.meta_recv.try_recv(){
+			self.meta=Some(meta);
+		}
+		self.recv_notifications();
+		if let Some(click)=data_model::MFMClick::take(ctx){
+			self.open_mfm_click(click);
+		}
+		self.recv_thread();
+		self.recv_search();
+		self.recv_reaction_users();
+		self.recv_note_updates();
+		self.flush_capture();
+		self.recv_deck(ctx);
+		self.recv_account();
+		if self.stream_state_recv.has_changed().unwrap_or(false){
+			self.stream_state=self.stream_state_recv.borrow_and_update().clone();
+			ctx.request_repaint();
+		}
+		if let Ok(user)=self.user_detail_recv.try_recv(){
+			self.load_relation(&user.user.id);
+			self.user_detail=Some(user);
+		}
+		if self.search.is_some(){
+			egui::SidePanel::right("search").resizable(true).default_width(400f32).show(ctx,|ui|{
+				ui.add_space(self.config.1.top.unwrap_or(0) as f32);
+				self.search_view(ui);
+			});
+		}
+		if self.view_thread.lock().unwrap().is_some(){
+			egui::SidePanel::right("thread").resizable(true).default_width(400f32).show(ctx,|ui|{
+				ui.add_space(self.config.1.top.unwrap_or(0) as f32);
+				self.thread_view(ui);
+			});
+		}
+		if self.view_notifications{
+			egui::SidePanel::right("notifications").resizable(true).show(ctx,|ui|{
+				ui.add_space(self.config.1.top.unwrap_or(0) as f32);
+				self.notifications(ui);
+			});
+		}
 		egui::CentralPanel::default().show(ctx, |ui| {
 			if let Ok(mut lock)=self.view_media.lock(){
 				if lock.is_some(){
@@ -179,6 +413,10 @@ impl <F> eframe::App for MainUI<F> where F:FnMut(&mut bool)+'static{
 			}
 			self.timeline(ui,ctx);
 		});
+		self.reaction_detail(ctx);
+		if self.debug_overlay{
+			self.debug_overlay(ctx);
+		}
 	}
 }
 impl <F> MainUI<F>{
@@ -248,8 +486,49 @@ impl <F> MainUI<F>{
 				self.state.write(&self.delay_assets);
 			}
 		});
+		if ui.checkbox(&mut self.state.deck_mode,&self.locale.deck_mode).changed(){
+			self.state.write(&self.delay_assets);
+			if !self.state.deck_mode{
+				self.close_deck();
+				self.open_timeline.lock().unwrap().replace((Some(self.state.timeline.clone()),None));
+			}
+		}
 		ui.heading(&self.locale.page_notes_count);
 		egui::Slider::new(&mut self.state.page_notes,5..=100).ui(ui);
+		self.cache_config(ui);
+		ui.checkbox(&mut self.debug_overlay,&self.locale.debug_overlay);
+	}
+	fn cache_config(&mut self,ui:&mut egui::Ui){
+		let cache=disk_cache::disk_cache();
+		ui.heading(&self.locale.image_cache);
+		let stats=cache.stats();
+		ui.label(format!("{} {} / {:.1}MB / {}MB",stats.entries,self.locale.cache_files,stats.size as f64/1024f64/1024f64,stats.max_size/1024/1024));
+		let mut size=self.state.cache_size_mb.unwrap_or(disk_cache::DEFAULT_CACHE_SIZE_MB);
+		let res=egui::Slider::new(&mut size,16..=4096).logarithmic(true).suffix("MB").ui(ui);
+		if res.changed(){
+			self.state.cache_size_mb=Some(size);
+			cache.set_max_size_mb(size);
+		}
+		if res.drag_stopped()||(res.changed()&&!res.dragged()){
+			self.state.write(&self.delay_assets);
+			//縮めた分を消す
+			std::thread::spawn(||{
+				tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(cache.trim());
+			});
+		}
+		if ui.button(&self.locale.clear_cache).clicked(){
+			std::thread::spawn(||{
+				tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(cache.clear());
+			});
+		}
+	}
+	fn debug_overlay(&self,ctx:&egui::Context){
+		let (textures,bytes)=data_model::texture_usage();
+		egui::Area::new(egui::Id::new("debug_overlay")).anchor(egui::Align2::RIGHT_BOTTOM,[-5f32,-5f32]).interactable(false).show(ctx,|ui|{
+			egui::Frame::popup(ui.style()).show(ui,|ui|{
+				ui.label(format!("GPU {} textures {:.1}MB / {}MB",textures,bytes as f64/1024f64/1024f64,data_model::DEFAULT_TEXTURE_BUDGET_MB));
+			});
+		});
 	}
 	fn media(&self,ui:&mut egui::Ui,lock:&mut Option<ZoomMediaView>){
 		fn view<F>(ui:&mut egui::Ui,img:egui::Image<'static>,close:F)where F:FnOnce()->(){
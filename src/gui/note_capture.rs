@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use crate::{data_model::Note, load_misskey::{NoteCapture, NoteUpdate}};
+
+use super::{composer::target_note, main_ui::MainUI};
+
+impl <F> MainUI<F>{
+	//単純なリノートはリノート元を購読する
+	pub(super) fn capture_note(&self,note:&Arc<Note>,sub:bool){
+		let id=target_note(note).id.clone();
+		let mut pending=self.pending_capture.lock().unwrap();
+		let count=pending.entry(id).or_insert(0);
+		*count+=if sub{1}else{-1};
+		drop(pending);
+		self.flush_capture();
+	}
+	//購読と解除が打ち消し合うものはまとめて、送れなかった分は次のフレームで送る
+	pub(super) fn flush_capture(&self){
+		let mut pending=self.pending_capture.lock().unwrap();
+		pending.retain(|_,count|*count!=0);
+		for (id,count) in pending.iter_mut(){
+			while *count!=0{
+				let capture=if *count>0{
+					NoteCapture::Sub(id.clone())
+				}else{
+					NoteCapture::Unsub(id.clone())
+				};
+				match self.capture.try_send(capture){
+					Ok(_)=>*count-=count.signum(),
+					Err(tokio::sync::mpsc::error::TrySendError::Full(_))=>return,
+					Err(e)=>{
+						eprintln!("capture send error {:?}",e);
+						return;
+					},
+				}
+			}
+		}
+	}
+	pub(super) fn clear_notes(&mut self){
+		for note in &self.notes{
+			self.capture_note(note,false);
+		}
+		self.notes.clear();
+	}
+	pub(super) fn recv_note_updates(&mut self){
+		while let Ok(update)=self.note_update_recv.try_recv(){
+			match update{
+				NoteUpdate::Updated(n)=>{
+					for old in self.notes.iter_mut(){
+						if old.id==n.id{
+							*old=n.clone();
+						}
+					}
+					self.update_thread_note(&n);
+					self.update_search_note(&n);
+					self.update_deck_note(&n);
+				},
+				NoteUpdate::Deleted(id)=>{
+					let mut removed=vec![];
+					self.notes.retain(|n|{
+						if n.id==id{
+							removed.push(n.clone());
+							false
+						}else{
+							true
+						}
+					});
+					for n in removed{
+						self.capture_note(&n,false);
+					}
+					self.remove_deck_note(&id);
+				},
+			}
+		}
+	}
+}
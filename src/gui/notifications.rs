@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use egui::{Color32, ScrollArea, Widget};
+
+use crate::{data_model::{Notification, NotificationKind}, load_misskey};
+
+use super::main_ui::MainUI;
+
+impl <F> MainUI<F>{
+	pub(super) fn recv_notifications(&mut self){
+		let mut received=false;
+		while let Ok((n,is_new))=self.notifications_recv.try_recv(){
+			if self.notifications.iter().any(|old|old.id==n.id){
+				continue;
+			}
+			if is_new{
+				self.unread_notifications+=1;
+			}
+			self.notifications.push(n);
+			received=true;
+		}
+		//新着があった時だけ並べ直す
+		if received{
+			self.notifications.sort_by(|a,b|b.created_at.cmp(&a.created_at));
+		}
+	}
+	pub(super) fn notifications_button(&mut self,ui:&mut egui::Ui){
+		let label=if self.unread_notifications>0{
+			egui::RichText::new(format!("{} ({})",self.locale.notifications,self.unread_notifications)).color(Color32::from_rgb(255,80,80))
+		}else{
+			egui::RichText::new(&self.locale.notifications)
+		};
+		if ui.button(label).clicked(){
+			self.view_notifications=!self.view_notifications;
+			if self.view_notifications{
+				self.unread_notifications=0;
+				if self.notifications.is_empty(){
+					let _=self.reload.blocking_send(load_misskey::LoadSrc::Notifications(None));
+				}
+			}
+		}
+	}
+	fn notification_label(&self,kind:&NotificationKind)->String{
+		match kind{
+			NotificationKind::Reaction => self.locale.notification_reaction.clone(),
+			NotificationKind::Renote => self.locale.renote.clone(),
+			NotificationKind::Quote => self.locale.notification_quote.clone(),
+			NotificationKind::Reply => self.locale.notification_reply.clone(),
+			NotificationKind::Mention => self.locale.notification_mention.clone(),
+			NotificationKind::Follow => self.locale.notification_follow.clone(),
+			NotificationKind::ReceiveFollowRequest => self.locale.notification_follow_request.clone(),
+			NotificationKind::FollowRequestAccepted => self.locale.notification_follow_accepted.clone(),
+			NotificationKind::Other(t) => t.clone(),
+		}
+	}
+	fn notification_ui(&self,ui:&mut egui::Ui,n:&Arc<Notification>){
+		ui.horizontal_wrapped(|ui|{
+			if let Some(user)=n.user.as_ref(){
+				let icon=self.get_image(&user.icon);
+				//ユーザーアイコン20x20
+				let icon=icon.max_size([20f32,20f32].into());
+				let icon=icon.rounding(egui::Rounding::from(10f32));
+				let icon=egui::Button::image(icon);
+				let icon=icon.fill(Color32::from_black_alpha(0));
+				if icon.ui(ui).clicked(){
+					self.open_timeline.lock().unwrap().replace((Some(load_misskey::TimeLine::User(user.id.clone())),None));
+				}
+				user.display_name.render(ui,true,&self.dummy,self.animate_frame);
+			}
+			ui.label(self.notification_label(&n.kind));
+			if let Some(reaction)=n.reaction.as_ref(){
+				let img=reaction.image(self.animate_frame).unwrap_or_else(||self.dummy.get(self.animate_frame).unwrap());
+				img.max_height(20f32).ui(ui);
+			}
+			ui.colored_label(Color32::from_gray(100),n.created_at_label());
+		});
+		if let Some(note)=n.note.as_ref(){
+			//通知対象のノートは要約して表示する
+			ui.horizontal_wrapped(|ui|{
+				ui.add_space(20f32);
+				ui.vertical(|ui|{
+					note.text.render(ui,false,&self.dummy,self.animate_frame);
+				});
+			});
+		}
+		ui.separator();
+	}
+	pub(super) fn notifications(&mut self,ui:&mut egui::Ui){
+		ui.heading(&self.locale.notifications);
+		self.follow_requests(ui);
+		ScrollArea::vertical().id_source("notifications").show(ui,|ui|{
+			for n in self.notifications.iter(){
+				self.notification_ui(ui,n);
+			}
+			if ui.button(&self.locale.load_more).clicked(){
+				let until_id=self.notifications.last().map(|n|n.id.clone());
+				let _=self.reload.blocking_send(load_misskey::LoadSrc::Notifications(until_id));
+			}
+		});
+	}
+}
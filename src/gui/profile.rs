@@ -0,0 +1,66 @@
+use egui::{Color32, Widget};
+
+use crate::data_model::UserDetail;
+
+use super::main_ui::MainUI;
+
+impl <F> MainUI<F>{
+	pub(super) fn user_header(&self,ui:&mut egui::Ui,detail:&UserDetail){
+		let user=&detail.user;
+		egui::CollapsingHeader::new(&self.locale.profile).id_source(("profile",&user.id)).default_open(true).show(ui,|ui|{
+			let width=ui.available_width();
+			if let Some(banner)=detail.banner.as_ref(){
+				//バナーは高さ120上限
+				self.get_image(banner).fit_to_exact_size([width,120f32].into()).ui(ui);
+			}
+			ui.horizontal_top(|ui|{
+				let icon=self.get_image(&user.icon);
+				let icon=icon.max_size([60f32,60f32].into());
+				let icon=icon.rounding(egui::Rounding::from(30f32));
+				icon.ui(ui);
+				ui.add_space(10f32);
+				ui.vertical(|ui|{
+					ui.horizontal_wrapped(|ui|{
+						ui.spacing_mut().item_spacing=[0f32,0f32].into();
+						user.display_name.render(ui,true,&self.dummy,self.animate_frame);
+					});
+					let host=user.instance.as_ref().map(|i|format!("@{}",i.host())).unwrap_or_default();
+					ui.colored_label(Color32::from_gray(100),format!("@{}{}",user.username,host));
+				});
+			});
+			self.relation_actions(ui,&user.id);
+			if let Some(description)=detail.description.as_ref(){
+				description.render(ui,false,&self.dummy,self.animate_frame);
+			}
+			if !detail.fields.is_empty(){
+				egui::Grid::new(("profile_fields",&user.id)).striped(true).show(ui,|ui|{
+					for (name,value) in &detail.fields{
+						name.render(ui,true,&self.dummy,self.animate_frame);
+						value.render(ui,false,&self.dummy,self.animate_frame);
+						ui.end_row();
+					}
+				});
+			}
+			ui.horizontal_wrapped(|ui|{
+				for (label,count) in [
+					(&self.locale.notes_count,detail.notes_count),
+					(&self.locale.following_count,detail.following_count),
+					(&self.locale.followers_count,detail.followers_count),
+				]{
+					ui.strong(count.to_string());
+					ui.colored_label(Color32::from_gray(100),label);
+					ui.add_space(10f32);
+				}
+				ui.colored_label(Color32::from_gray(100),format!("{} {}",self.locale.joined_at,detail.created_at_label()));
+			});
+			if !detail.pinned_notes.is_empty(){
+				egui::CollapsingHeader::new(&self.locale.pinned_notes).id_source(("pinned_notes",&user.id)).show(ui,|ui|{
+					for note in &detail.pinned_notes{
+						self.note_ui(ui,note);
+					}
+				});
+			}
+		});
+		ui.separator();
+	}
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use egui::{Color32, ScrollArea, Widget};
+
+use crate::{data_model::{Note, UserProfile}, load_misskey};
+
+use super::main_ui::MainUI;
+
+pub(super) struct ReactionDetail{
+	note_id:String,
+	reaction:String,
+	img:Option<egui::Image<'static>>,
+	count:u64,
+	users:Vec<(String,Arc<UserProfile>)>,
+	loading:bool,
+	has_more:bool,
+	error:Option<String>,
+	pos:egui::Pos2,
+	just_opened:bool,
+}
+impl <F> MainUI<F>{
+	pub(super) fn open_reaction_detail(&self,note:&Note,reaction:&str,img:Option<egui::Image<'static>>,count:u64,pos:egui::Pos2){
+		let mut lock=self.reaction_detail.lock().unwrap();
+		if lock.as_ref().map(|d|d.note_id==note.id&&d.reaction==reaction).unwrap_or(false){
+			return;
+		}
+		let loading=self.reload.try_send(load_misskey::LoadSrc::Reactions(note.id.clone(),reaction.to_owned(),None)).is_ok();
+		*lock=Some(ReactionDetail{
+			note_id:note.id.clone(),
+			reaction:reaction.to_owned(),
+			img,
+			count,
+			users:vec![],
+			loading,
+			has_more:!loading,
+			error:None,
+			pos,
+			just_opened:true,
+		});
+	}
+	pub(super) fn recv_reaction_users(&mut self){
+		while let Ok(res)=self.reaction_users_recv.try_recv(){
+			let mut lock=self.reaction_detail.lock().unwrap();
+			let detail=match lock.as_mut(){
+				Some(d) if d.note_id==res.note_id&&d.reaction==res.reaction=>d,
+				_=>continue,
+			};
+			detail.loading=false;
+			let users=match res.users{
+				Ok(users)=>users,
+				Err(e)=>{
+					//続きのボタンで再試行できるようにする
+					detail.error=Some(e);
+					detail.has_more=true;
+					continue;
+				}
+			};
+			detail.has_more=!users.is_empty();
+			if !res.is_next{
+				detail.users.clear();
+			}
+			detail.users.extend(users);
+		}
+	}
+	pub(super) fn reaction_detail(&self,ctx:&egui::Context){
+		let mut lock=self.reaction_detail.lock().unwrap();
+		let detail=match lock.as_mut(){
+			Some(d)=>d,
+			None=>return,
+		};
+		let mut close=false;
+		let mut open_user=None;
+		let res=egui::Window::new("reaction_detail").title_bar(false).resizable(false).collapsible(false).fixed_pos(detail.pos).show(ctx,|ui|{
+			ui.horizontal(|ui|{
+				if let Some(img)=detail.img.clone(){
+					img.max_height(20f32).ui(ui);
+				}
+				ui.label(detail.count.to_string());
+				if ui.button(&self.locale.close).clicked(){
+					close=true;
+				}
+			});
+			ScrollArea::vertical().id_source("reaction_detail").max_height(240f32).show(ui,|ui|{
+				for (_,user) in &detail.users{
+					ui.horizontal(|ui|{
+						let icon=self.get_image(&user.icon);
+						let icon=icon.max_size([20f32,20f32].into());
+						let icon=icon.rounding(egui::Rounding::from(10f32));
+						let icon=egui::Button::image(icon);
+						let icon=icon.fill(Color32::from_black_alpha(0));
+						if icon.ui(ui).clicked(){
+							open_user=Some(user.id.clone());
+						}
+						user.display_name.render(ui,true,&self.dummy,self.animate_frame);
+						let host=user.instance.as_ref().map(|i|format!("@{}",i.host())).unwrap_or_default();
+						if ui.link(format!("@{}{}",user.username,host)).clicked(){
+							open_user=Some(user.id.clone());
+						}
+					});
+				}
+				if let Some(e)=detail.error.as_ref(){
+					ui.colored_label(Color32::from_rgb(255,0,0),e);
+				}
+				if detail.loading{
+					egui::ProgressBar::new(0f32).animate(true).ui(ui);
+				}else if detail.has_more{
+					if ui.button(&self.locale.load_more).clicked(){
+						detail.error=None;
+						let until_id=detail.users.last().map(|(id,_)|id.clone());
+						let src=load_misskey::LoadSrc::Reactions(detail.note_id.clone(),detail.reaction.clone(),until_id);
+						detail.loading=self.reload.try_send(src).is_ok();
+					}
+				}
+			});
+		});
+		//枠外をクリックしたら閉じる
+		if detail.just_opened{
+			detail.just_opened=false;
+		}else if let Some(res)=res{
+			if ctx.input(|i|i.pointer.any_click())&&!res.response.contains_pointer(){
+				close=true;
+			}
+		}
+		if let Some(id)=open_user{
+			self.open_timeline.lock().unwrap().replace((Some(load_misskey::TimeLine::User(id)),None));
+			close=true;
+		}
+		if close{
+			*lock=None;
+		}
+	}
+}
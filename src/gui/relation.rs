@@ -0,0 +1,207 @@
+use egui::{Color32, Widget};
+use serde::{Deserialize, Serialize};
+
+use crate::load_misskey;
+
+use super::main_ui::MainUI;
+
+#[derive(Clone,Debug,Deserialize)]
+pub(super) struct UserRelation{
+	id:String,
+	#[serde(rename = "isFollowing")]
+	is_following:bool,
+	#[serde(rename = "hasPendingFollowRequestFromYou")]
+	has_pending_follow_request_from_you:bool,
+	#[serde(rename = "isFollowed")]
+	is_followed:bool,
+	#[serde(rename = "isBlocking")]
+	is_blocking:bool,
+	#[serde(rename = "isMuted")]
+	is_muted:bool,
+}
+#[derive(Clone,Debug,Deserialize)]
+pub(super) struct FollowRequest{
+	id:String,
+	follower:FollowRequestUser,
+}
+#[derive(Clone,Debug,Deserialize)]
+struct FollowRequestUser{
+	id:String,
+	name:Option<String>,
+	username:String,
+	host:Option<String>,
+}
+#[derive(Debug,Serialize)]
+struct UserIdPayload{
+	#[serde(rename = "userId")]
+	user_id:String,
+	i:String,
+}
+impl <F> MainUI<F>{
+	fn user_id_post(&self,endpoint:&str,user_id:&str)->Option<(reqwest::StatusCode,Vec<u8>)>{
+		let build=self.client.post(format!("{}/api/{}",self.config.1.instance.as_ref().unwrap(),endpoint));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		let payload=UserIdPayload{
+			user_id:user_id.to_owned(),
+			i:self.config.1.token.as_ref().unwrap().clone(),
+		};
+		let build=build.body(serde_json::to_string(&payload).unwrap());
+		tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+			match build.send().await{
+				Ok(res)=>{
+					let status=res.status();
+					println!("{} {}",endpoint,status);
+					res.bytes().await.ok().map(|b|(status,b.to_vec()))
+				},
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					None
+				}
+			}
+		})
+	}
+	pub(super) fn load_relation(&self,user_id:&str){
+		let relation=self.user_id_post("users/relation",user_id).and_then(|(_,b)|serde_json::from_slice::<UserRelation>(&b).ok());
+		*self.relation.lock().unwrap()=relation;
+	}
+	//成功したら関係を読み直す
+	fn relation_send(&self,endpoint:&str,user_id:&str)->bool{
+		let ok=self.user_id_post(endpoint,user_id).map(|(status,_)|status.is_success()).unwrap_or(false);
+		if ok{
+			self.load_relation(user_id);
+		}
+		ok
+	}
+	pub(super) fn relation_actions(&self,ui:&mut egui::Ui,user_id:&str){
+		let relation=self.relation.lock().unwrap().clone();
+		let relation=match relation{
+			Some(relation) if relation.id==user_id=>relation,
+			_=>return,
+		};
+		ui.horizontal_wrapped(|ui|{
+			if relation.is_followed{
+				ui.colored_label(Color32::from_gray(100),&self.locale.followed_by);
+			}
+			if relation.is_following{
+				if ui.button(&self.locale.unfollow).clicked(){
+					self.relation_send("following/delete",user_id);
+				}
+			}else if relation.has_pending_follow_request_from_you{
+				if ui.button(&self.locale.follow_requested).clicked(){
+					self.relation_send("following/requests/cancel",user_id);
+				}
+			}else if !relation.is_blocking{
+				if ui.button(&self.locale.follow).clicked(){
+					self.relation_send("following/create",user_id);
+				}
+			}
+			if relation.is_muted{
+				if ui.button(&self.locale.unmute).clicked(){
+					self.relation_send("mute/delete",user_id);
+				}
+			}else if ui.button(&self.locale.mute).clicked(){
+				self.relation_send("mute/create",user_id);
+			}
+			if relation.is_blocking{
+				if ui.button(&self.locale.unblock).clicked(){
+					self.relation_send("blocking/delete",user_id);
+				}
+			}else{
+				//ブロックは確認してから送る
+				let confirm_id=egui::Id::new(("block_confirm",user_id));
+				let confirm=ui.data(|d|d.get_temp::<bool>(confirm_id)).unwrap_or(false);
+				let block=||egui::Button::new(egui::RichText::new(&self.locale.block).color(Color32::from_rgb(255,80,80)));
+				if confirm{
+					ui.label(&self.locale.block_confirm);
+					if block().ui(ui).clicked(){
+						self.relation_send("blocking/create",user_id);
+						ui.data_mut(|d|d.remove::<bool>(confirm_id));
+					}
+					if ui.button(&self.locale.cancel).clicked(){
+						ui.data_mut(|d|d.remove::<bool>(confirm_id));
+					}
+				}else if block().ui(ui).clicked(){
+					ui.data_mut(|d|d.insert_temp(confirm_id,true));
+				}
+			}
+		});
+	}
+	pub(super) fn load_follow_requests(&mut self){
+		let build=self.client.post(format!("{}/api/following/requests/list",self.config.1.instance.as_ref().unwrap()));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		#[derive(Debug,Serialize)]
+		struct ListPayload{
+			i:String,
+		}
+		let payload=ListPayload{
+			i:self.config.1.token.as_ref().unwrap().clone(),
+		};
+		let build=build.body(serde_json::to_string(&payload).unwrap());
+		let requests=tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+			let res=match build.send().await{
+				Ok(res)=>res,
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					return vec![];
+				}
+			};
+			println!("following/requests/list {}",res.status());
+			match res.bytes().await.map(|b|serde_json::from_slice::<Vec<FollowRequest>>(&b)){
+				Ok(Ok(requests))=>requests,
+				Ok(Err(e))=>{
+					eprintln!("{:?}",e);
+					vec![]
+				},
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					vec![]
+				}
+			}
+		});
+		self.follow_requests=Some(requests);
+	}
+	pub(super) fn follow_requests(&mut self,ui:&mut egui::Ui){
+		let mut load=false;
+		let mut done=None;
+		egui::CollapsingHeader::new(&self.locale.follow_requests).id_source("follow_requests").show(ui,|ui|{
+			let requests=match self.follow_requests.as_ref(){
+				Some(requests)=>requests,
+				None=>{
+					load=true;
+					return;
+				}
+			};
+			for (idx,req) in requests.iter().enumerate(){
+				let user=&req.follower;
+				ui.horizontal_wrapped(|ui|{
+					let host=user.host.as_ref().map(|h|format!("@{}",h)).unwrap_or_default();
+					let acct=format!("@{}{}",user.username,host);
+					if ui.link(user.name.as_ref().unwrap_or(&user.username)).on_hover_text(&acct).clicked(){
+						self.open_timeline.lock().unwrap().replace((Some(load_misskey::TimeLine::User(user.id.clone())),None));
+					}
+					ui.colored_label(Color32::from_gray(100),acct);
+					if ui.button(&self.locale.accept).clicked(){
+						if self.user_id_post("following/requests/accept",&user.id).map(|(status,_)|status.is_success()).unwrap_or(false){
+							done=Some(idx);
+						}
+					}
+					if ui.button(&self.locale.reject).clicked(){
+						if self.user_id_post("following/requests/reject",&user.id).map(|(status,_)|status.is_success()).unwrap_or(false){
+							done=Some(idx);
+						}
+					}
+				});
+			}
+			if ui.button(&self.locale.reload).clicked(){
+				load=true;
+			}
+		});
+		if let Some(idx)=done{
+			let req=self.follow_requests.as_mut().unwrap().remove(idx);
+			println!("follow request {} done",req.id);
+		}
+		if load{
+			self.load_follow_requests();
+		}
+	}
+}
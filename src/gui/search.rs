@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use egui::{Color32, ScrollArea, Widget};
+
+use crate::{data_model::Note, load_misskey};
+
+use super::main_ui::MainUI;
+
+#[derive(Default)]
+pub(super) struct SearchView{
+	query:String,
+	user:String,
+	host:String,
+	notes:Vec<Arc<Note>>,
+	loading:bool,
+	has_more:bool,
+	error:Option<String>,
+}
+impl <F> MainUI<F>{
+	pub(super) fn recv_search(&mut self){
+		while let Ok(res)=self.search_recv.try_recv(){
+			let search=match self.search.as_mut(){
+				Some(search)=>search,
+				None=>continue,
+			};
+			search.loading=false;
+			let (notes,is_next)=match res{
+				Ok(res)=>res,
+				Err(e)=>{
+					search.error=Some(e);
+					continue;
+				}
+			};
+			search.has_more=!notes.is_empty();
+			if !is_next{
+				search.notes.clear();
+			}
+			for n in notes{
+				if !search.notes.iter().any(|old|old.id==n.id){
+					search.notes.push(n);
+				}
+			}
+		}
+	}
+	pub(super) fn update_search_note(&mut self,n:&Arc<Note>){
+		if let Some(search)=self.search.as_mut(){
+			for old in search.notes.iter_mut(){
+				if old.id==n.id{
+					*old=n.clone();
+				}
+			}
+		}
+	}
+	fn search_send(&self,search:&mut SearchView,until_id:Option<String>){
+		search.error=None;
+		//@user@host形式のユーザー指定をIDに変換する
+		let user_id=if search.user.trim().is_empty(){
+			None
+		}else{
+			let mut acct=search.user.trim().trim_start_matches('@').splitn(2,'@');
+			let username=acct.next().unwrap_or_default().to_owned();
+			let host=acct.next().map(|s|s.to_owned());
+			let id=tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+				self.user_id(username,host).await
+			});
+			match id{
+				Some(id)=>Some(id),
+				None=>{
+					search.error=Some(self.locale.user_not_found.clone());
+					return;
+				}
+			}
+		};
+		let host=search.host.trim();
+		let opt=load_misskey::SearchOption{
+			query:search.query.clone(),
+			user_id,
+			host:if host.is_empty(){
+				None
+			}else{
+				Some(host.to_owned())
+			},
+			until_id,
+		};
+		if self.reload.blocking_send(load_misskey::LoadSrc::Search(opt)).is_ok(){
+			search.loading=true;
+		}
+	}
+	//MFMの検索ブロックから開く
+	pub(super) fn open_search(&mut self,query:String){
+		let mut search=SearchView{
+			query,
+			..Default::default()
+		};
+		self.search_send(&mut search,None);
+		self.search=Some(search);
+	}
+	pub(super) fn search_view(&mut self,ui:&mut egui::Ui){
+		let mut search=match self.search.take(){
+			Some(search)=>search,
+			None=>return,
+		};
+		let mut close=false;
+		ui.horizontal(|ui|{
+			ui.heading(&self.locale.search);
+			if ui.button(&self.locale.close).clicked(){
+				close=true;
+			}
+		});
+		let res=egui::TextEdit::singleline(&mut search.query).hint_text(&self.locale.search_keyword).desired_width(f32::INFINITY).ui(ui);
+		let enter=res.lost_focus()&&ui.input(|i|i.key_pressed(egui::Key::Enter));
+		egui::TextEdit::singleline(&mut search.user).hint_text(&self.locale.search_user).desired_width(f32::INFINITY).ui(ui);
+		egui::TextEdit::singleline(&mut search.host).hint_text(&self.locale.search_host).desired_width(f32::INFINITY).ui(ui);
+		ui.horizontal(|ui|{
+			let bt=egui::Button::new(&self.locale.search);
+			let can_search=!search.query.trim().is_empty()&&!search.loading;
+			if ui.add_enabled(can_search,bt).clicked()||(enter&&can_search){
+				self.search_send(&mut search,None);
+			}
+			if search.loading{
+				egui::ProgressBar::new(0f32).desired_width(10f32).animate(true).ui(ui);
+			}
+			if let Some(e)=search.error.as_ref(){
+				ui.colored_label(Color32::from_rgb(255,0,0),e);
+			}
+		});
+		ui.separator();
+		let mut load_more=false;
+		ScrollArea::vertical().id_source("search").show_viewport(ui,|ui,rect|{
+			let mut y=0f32;
+			for note in &search.notes{
+				self.viewport_note(ui,rect,&mut y,note,|ui|self.note_ui(ui,note));
+			}
+			if search.has_more&&!search.loading{
+				if ui.button(&self.locale.load_more).clicked(){
+					load_more=true;
+				}
+			}
+		});
+		if load_more{
+			let until_id=search.notes.last().map(|n|n.id.clone());
+			self.search_send(&mut search,until_id);
+		}
+		if !close{
+			self.search=Some(search);
+		}
+	}
+}
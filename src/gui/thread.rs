@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use egui::{Color32, ScrollArea, Widget};
+
+use crate::{data_model::Note, load_misskey};
+
+use super::{composer::target_note, main_ui::MainUI};
+
+impl <F> MainUI<F>{
+	pub(super) fn open_thread(&self,note:&Arc<Note>){
+		self.open_thread_id(target_note(note).id.clone());
+	}
+	fn open_thread_id(&self,id:String){
+		self.view_thread.lock().unwrap().replace(id.clone());
+		self.thread_error.lock().unwrap().take();
+		let _=self.reload.blocking_send(load_misskey::LoadSrc::Thread(id));
+	}
+	//ノート上部の返信先表示
+	pub(super) fn reply_context(&self,ui:&mut egui::Ui,note:&Note){
+		let res=egui::Frame::none().inner_margin(egui::Margin::same(2f32)).show(ui,|ui|{
+			ui.horizontal_wrapped(|ui|{
+				ui.colored_label(Color32::from_gray(100),&self.locale.reply_to);
+				if let Some(reply)=note.reply.as_ref(){
+					let icon=self.get_image(&reply.user.icon);
+					let icon=icon.max_size([16f32,16f32].into());
+					let icon=icon.rounding(egui::Rounding::from(8f32));
+					icon.ui(ui);
+					reply.user.display_name.render(ui,true,&self.dummy,self.animate_frame);
+					let len=reply.text.raw.char_indices().nth(50).map(|(v,_)|v).unwrap_or(reply.text.raw.len());
+					ui.colored_label(Color32::from_gray(100),&reply.text.raw[..len]);
+				}
+			});
+		});
+		let id=note.reply.as_ref().map(|n|n.id.clone()).or_else(||note.reply_id.clone());
+		let res=ui.interact(res.response.rect,ui.id().with(("reply_context",&note.id)),egui::Sense::click());
+		if res.on_hover_cursor(egui::CursorIcon::PointingHand).clicked(){
+			if let Some(id)=id{
+				self.open_thread_id(id);
+			}
+		}
+	}
+	pub(super) fn recv_thread(&mut self){
+		while let Ok(res)=self.thread_recv.try_recv(){
+			let id=match &res{
+				Ok(thread)=>&thread.id,
+				Err((id,_))=>id,
+			};
+			//閉じた後や別のノートを開いた後に届いたものは捨てる
+			if self.view_thread.lock().unwrap().as_ref()!=Some(id){
+				continue;
+			}
+			match res{
+				Ok(thread)=>self.thread=Some(thread),
+				Err(e)=>*self.thread_error.lock().unwrap()=Some(e),
+			}
+		}
+	}
+	pub(super) fn update_thread_note(&mut self,n:&Arc<Note>){
+		let thread=match self.thread.as_mut(){
+			Some(thread)=>thread,
+			None=>return,
+		};
+		let notes=thread.ancestors.iter_mut().chain(thread.note.iter_mut()).chain(thread.children.iter_mut().map(|(_,n)|n));
+		for old in notes{
+			if old.id==n.id{
+				*old=n.clone();
+			}
+		}
+	}
+	pub(super) fn thread_view(&mut self,ui:&mut egui::Ui){
+		ui.horizontal(|ui|{
+			ui.heading(&self.locale.thread);
+			if ui.button(&self.locale.close).clicked(){
+				self.view_thread.lock().unwrap().take();
+				self.thread=None;
+			}
+		});
+		let id=self.view_thread.lock().unwrap().clone();
+		let thread=match self.thread.as_ref(){
+			Some(thread) if Some(&thread.id)==id.as_ref()=>thread,
+			_=>{
+				let error=self.thread_error.lock().unwrap().clone().filter(|(error_id,_)|Some(error_id)==id.as_ref());
+				match error{
+					Some((error_id,e))=>{
+						ui.colored_label(Color32::from_rgb(255,0,0),e);
+						if ui.button(&self.locale.reload).clicked(){
+							self.open_thread_id(error_id);
+						}
+					},
+					None=>{
+						egui::ProgressBar::new(0f32).animate(true).ui(ui);
+					},
+				}
+				return;
+			}
+		};
+		ScrollArea::vertical().id_source("thread").show_viewport(ui,|ui,rect|{
+			let mut y=0f32;
+			for note in &thread.ancestors{
+				self.viewport_note(ui,rect,&mut y,note,|ui|self.note_ui(ui,note));
+			}
+			if let Some(note)=thread.note.as_ref(){
+				self.viewport_note(ui,rect,&mut y,note,|ui|{
+					egui::Frame::none().stroke(egui::Stroke::new(1f32,Color32::from_gray(150))).inner_margin(egui::Margin::same(4f32)).show(ui,|ui|{
+						self.note_ui(ui,note);
+					});
+				});
+			}
+			for (depth,note) in &thread.children{
+				self.viewport_note(ui,rect,&mut y,note,|ui|{
+					ui.horizontal_top(|ui|{
+						ui.add_space(*depth as f32*15f32);
+						ui.vertical(|ui|{
+							self.note_ui(ui,note);
+						});
+					});
+				});
+			}
+		});
+	}
+}
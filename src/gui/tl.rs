@@ -2,12 +2,19 @@ use std::sync::Arc;
 
 use egui::{Color32, ScrollArea, Widget};
 
-use crate::{data_model::{self, DelayAssets, LocalEmojis, Note, Summaly, UrlImage}, gui::utils::ZoomMediaView, load_misskey::{self, TimeLine}};
+use crate::{data_model::{self, DelayAssets, LocalEmojis, Note, Summaly, UrlImage}, gui::utils::ZoomMediaView, load_misskey::{self, StreamState, TimeLine}};
 
 use super::main_ui::MainUI;
 
 impl <F> MainUI<F>{
 	fn load(&mut self,tl:Option<TimeLine>,until_id:Option<String>){
+		//デッキ表示中は開いたタイムラインを列として追加する
+		if self.state.deck_mode{
+			if let Some(tl)=tl{
+				self.add_column(tl);
+			}
+			return;
+		}
 		self.view_old_timeline=2f32;
 		let reload=self.reload.clone();
 		if reload.max_capacity()==reload.capacity(){
@@ -15,14 +22,14 @@ impl <F> MainUI<F>{
 			if let Some(tl)=&tl{
 				if self.state.timeline!=*tl{
 					self.state.timeline=tl.clone();
-					self.notes.clear();
+					self.clear_notes();
 					if until_id.is_none(){
 						self.state.write(&self.delay_assets);
 					}
 				}
 			}
 			if until_id.is_some(){
-				self.notes.clear();
+				self.clear_notes();
 				self.state.write(&self.delay_assets);
 			}
 			let known_notes=self.notes.clone();
@@ -33,6 +40,7 @@ impl <F> MainUI<F>{
 				tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async{
 					let _=reload.send(load_misskey::LoadSrc::TimeLine(load_misskey::TLOption{
 						until_id,
+						since_id:None,
 						limit,
 						tl,
 						known_notes,
@@ -50,17 +58,48 @@ impl <F> MainUI<F>{
 		}{
 			self.load(tl,until_id);
 		}
+		let logged_in=self.config.1.token.is_some()&&self.config.1.instance.is_some();
+		if self.state.deck_mode&&self.deck.is_none()&&logged_in{
+			self.open_deck();
+		}
 		ui.horizontal_wrapped(|ui|{
 			ui.heading(&self.locale.appname);
+			self.account_switcher(ui);
 			if ui.button("HTL").clicked(){
 				self.load(Some(load_misskey::TimeLine::Home),None);
 			}
+			if self.meta.as_ref().map(|m|m.features.local_timeline.unwrap_or(true)).unwrap_or(true){
+				if ui.button("LTL").clicked(){
+					self.load(Some(load_misskey::TimeLine::Local),None);
+				}
+				if ui.button("STL").clicked(){
+					self.load(Some(load_misskey::TimeLine::Social),None);
+				}
+			}
 			if ui.button("GTL").clicked(){
 				self.load(Some(load_misskey::TimeLine::Global),None);
 			}
-			if ui.checkbox(&mut self.auto_update,&self.locale.websocket).changed(){
+			self.notifications_button(ui);
+			if ui.button(&self.locale.search).clicked(){
+				self.search=match self.search.take(){
+					Some(_)=>None,
+					None=>Some(Default::default()),
+				};
+			}
+			if ui.button(&self.locale.lists).clicked(){
+				if self.timeline_picker.take().is_none(){
+					let lists=self.named_items("users/lists/list");
+					let antennas=self.named_items("antennas/list");
+					self.timeline_picker=Some((lists,antennas));
+				}
+			}
+			//デッキの列は常にストリーミングで受け取る
+			if !self.state.deck_mode&&ui.checkbox(&mut self.auto_update,&self.locale.websocket).changed(){
 				self.load(None,None);
 			}
+			if self.auto_update||self.state.deck_mode{
+				self.stream_state(ui);
+			}
 			if self.view_old_timeline>=1f32&&self.view_old_timeline<2f32{
 				if let Some(n)=self.notes.first().map(|n|n.id.to_string()){
 					self.auto_update=false;
@@ -78,15 +117,35 @@ impl <F> MainUI<F>{
 				});
 			}
 		});
-		if self.config.1.token.is_none(){
-			ui.heading("tokenが指定されていません");
-			ui.label(format!("{}を編集してください",self.config.0));
+		if let Some((lists,antennas))=&self.timeline_picker{
+			let mut open=None;
+			ui.horizontal_wrapped(|ui|{
+				ui.label(&self.locale.lists);
+				for list in lists{
+					if ui.button(&list.name).clicked(){
+						open=Some(load_misskey::TimeLine::List(list.id.clone()));
+					}
+				}
+			});
+			ui.horizontal_wrapped(|ui|{
+				ui.label(&self.locale.antennas);
+				for antenna in antennas{
+					if ui.button(&antenna.name).clicked(){
+						open=Some(load_misskey::TimeLine::Antenna(antenna.id.clone()));
+					}
+				}
+			});
+			if let Some(tl)=open{
+				self.timeline_picker=None;
+				self.load(Some(tl),None);
+			}
 		}
-		if self.config.1.instance.is_none(){
-			ui.heading("instanceが指定されていません(https://misskey.example.com)");
-			ui.label(format!("{}を編集してください",self.config.0));
+		if self.config.1.token.is_none()||self.config.1.instance.is_none()||self.login.is_some(){
+			self.login(ui);
+		}
+		if self.config.1.token.is_some()&&self.config.1.instance.is_some(){
+			self.composer(ui);
 		}
-		ui.text_edit_singleline(&mut self.input_text);
 		if let Ok(n)=self.rcv.try_recv(){
 			//blurhashは即座に読み込む
 			tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async{
@@ -112,16 +171,29 @@ impl <F> MainUI<F>{
 				}
 				idx+=1;
 			}
+			self.update_thread_note(&n);
+			self.update_search_note(&n);
 			if let Some(rm)=index{
 				//同一ノート内容更新
 				self.notes.remove(rm);
 				self.notes.insert(rm,n);
 			}else{
+				self.capture_note(&n,true);
 				self.notes.push(n);
 				self.view_old_timeline=0f32;
 			}
 			if self.notes.len()>self.state.page_notes as usize{
-				self.notes.remove(0);
+				let n=self.notes.remove(0);
+				self.capture_note(&n,false);
+			}
+		}
+		if self.state.deck_mode{
+			self.deck_view(ui);
+			return;
+		}
+		if let TimeLine::User(id)=&self.state.timeline{
+			if let Some(user)=self.user_detail.as_ref().filter(|u|&u.user.id==id){
+				self.user_header(ui,user);
 			}
 		}
 	/*
@@ -130,27 +202,9 @@ impl <F> MainUI<F>{
 		}
 	*/
 		let scroll=ScrollArea::vertical().show_viewport(ui,|ui,rect|{
-			let width=ui.available_width();
 			let mut y=0f32;
 			for note in self.notes.iter().rev(){
-				let height=f32::from_bits(note.height.load(std::sync::atomic::Ordering::Relaxed));
-				if height>0.5&&(rect.min.y>y+height||y>rect.max.y){
-					ui.spacing_mut().item_spacing=[0f32,0f32].into();
-					ui.vertical(|ui|{
-						ui.spacing_mut().item_spacing=[0f32,0f32].into();
-						ui.add_space(height);
-					});
-					y+=height;
-				}else{
-					ui.spacing_mut().item_spacing=[5f32,0f32].into();
-					let res=ui.allocate_ui([width,0f32].into(),|ui|{
-						//ui.label(format!("{}",height));
-						self.note_ui(ui,note);
-					});
-					let h=res.response.rect.height();
-					note.height.store(h.to_bits(),std::sync::atomic::Ordering::Relaxed);
-					y+=h;
-				}
+				self.viewport_note(ui,rect,&mut y,note,|ui|self.note_ui(ui,note));
 			}
 			if !self.state.auto_old_timeline{
 				if egui::Button::new(&self.locale.load_old_timeline).ui(ui).clicked(){
@@ -172,6 +226,100 @@ impl <F> MainUI<F>{
 			}
 		}
 	}
+	fn stream_state(&mut self,ui:&mut egui::Ui){
+		let (label,color)=match &self.stream_state{
+			StreamState::Disconnected=>(&self.locale.stream_disconnected,Color32::from_gray(100)),
+			StreamState::Connecting=>(&self.locale.stream_connecting,Color32::from_rgb(200,160,0)),
+			StreamState::Connected=>(&self.locale.stream_connected,Color32::from_rgb(0,160,0)),
+			StreamState::Reconnecting=>(&self.locale.stream_reconnecting,Color32::from_rgb(200,160,0)),
+			StreamState::Failed(_)=>(&self.locale.stream_failed,Color32::from_rgb(255,80,80)),
+		};
+		let res=ui.colored_label(color,label);
+		if let StreamState::Failed(e)=&self.stream_state{
+			res.on_hover_text(e);
+		}
+		match self.stream_state{
+			StreamState::Reconnecting|StreamState::Failed(_)=>{
+				if ui.button(&self.locale.reconnect).clicked(){
+					if self.state.deck_mode{
+						//次のフレームで列を開き直す
+						self.close_deck();
+					}else{
+						self.load(None,None);
+					}
+				}
+			},
+			_=>{},
+		}
+	}
+	//show_viewportの中でノートを1つ描画する。表示範囲外は高さだけ確保する
+	pub(super) fn viewport_note(&self,ui:&mut egui::Ui,rect:egui::Rect,y:&mut f32,note:&Arc<Note>,render:impl FnOnce(&mut egui::Ui)){
+		let height=f32::from_bits(note.height.load(std::sync::atomic::Ordering::Relaxed));
+		if height>0.5&&(rect.min.y>*y+height||*y>rect.max.y){
+			//表示範囲から1画面以上離れたノートの画像は予算を超えたら捨てる
+			let margin=rect.height();
+			if data_model::over_texture_budget()&&(rect.min.y-margin>*y+height||*y>rect.max.y+margin){
+				note.evict_images();
+			}
+			ui.spacing_mut().item_spacing=[0f32,0f32].into();
+			ui.vertical(|ui|{
+				ui.spacing_mut().item_spacing=[0f32,0f32].into();
+				ui.add_space(height);
+			});
+			*y+=height;
+		}else{
+			ui.spacing_mut().item_spacing=[5f32,0f32].into();
+			//捨てた画像をディスクキャッシュから読み直す
+			if note.take_evicted(){
+				let _=self.delay_assets.try_send(data_model::DelayAssets::Note(note.clone()));
+			}
+			let width=ui.available_width();
+			let res=ui.allocate_ui([width,0f32].into(),render);
+			let h=res.response.rect.height();
+			note.height.store(h.to_bits(),std::sync::atomic::Ordering::Relaxed);
+			*y+=h;
+		}
+	}
+	//ボタンごとにホバーし始めた時刻を持つ。スクロールで下に来ただけなら数えず、開いたら離れるまで開かない
+	fn hover_dwell(&self,ui:&egui::Ui,res:&egui::Response)->bool{
+		let id=res.id.with("hover_dwell");
+		let (now,scrolling)=ui.input(|i|(i.time,i.raw_scroll_delta!=egui::Vec2::ZERO||i.smooth_scroll_delta!=egui::Vec2::ZERO));
+		if !res.hovered()||scrolling{
+			ui.data_mut(|d|d.remove::<f64>(id));
+			return false;
+		}
+		let start=ui.data_mut(|d|*d.get_temp_mut_or(id,now));
+		if start.is_infinite(){
+			false
+		}else if now-start>0.8{
+			ui.data_mut(|d|d.insert_temp(id,f64::INFINITY));
+			true
+		}else{
+			ui.ctx().request_repaint_after(std::time::Duration::from_secs_f64(0.8-(now-start)+0.05));
+			false
+		}
+	}
+	//アンケートの選択肢と票数
+	fn poll_ui(&self,ui:&mut egui::Ui,poll:&data_model::Poll){
+		let total=poll.total_votes();
+		let width=ui.available_width();
+		for choice in &poll.choices{
+			let rate=if total==0{
+				0f32
+			}else{
+				choice.votes as f32/total as f32
+			};
+			let text=if choice.is_voted{
+				format!("✔ {} ({}{})",choice.text,choice.votes,self.locale.poll_votes)
+			}else{
+				format!("{} ({}{})",choice.text,choice.votes,self.locale.poll_votes)
+			};
+			egui::ProgressBar::new(rate).desired_width(width).text(text).ui(ui);
+		}
+		if poll.is_expired(){
+			ui.weak(&self.locale.poll_closed);
+		}
+	}
 	fn url_summaly(&self,url:&str,summaly:&Arc<tokio::sync::Mutex<Option<Summaly>>>,ui:&mut egui::Ui){
 		let mut sub_ui=ui.child_ui_with_id_source(ui.available_rect_before_wrap(),egui::Layout::left_to_right(egui::Align::Min).with_main_wrap(true),url);
 		let sub_ui=&mut sub_ui;
@@ -225,15 +373,17 @@ impl <F> MainUI<F>{
 			//プレビュー拡大
 			if let Some(thumbnail)=thumbnail{
 				if let Ok(mut lock)=self.view_media.lock(){
+					let original_img=Arc::new(thumbnail.full_resolution());
+					let _=self.delay_assets.blocking_send(data_model::DelayAssets::Image(original_img.clone()));
 					*lock=Some(ZoomMediaView{
-						original_img:thumbnail.clone(),
+						original_img,
 						preview:None,
 					});
 				}
 			}
 		}
 	}
-	fn note_ui(&self,ui:&mut egui::Ui,note:&Arc<Note>){
+	pub(super) fn note_ui(&self,ui:&mut egui::Ui,note:&Arc<Note>){
 		if let Some(quote)=note.quote.as_ref(){
 			if !note.is_simple_renote(){
 				self.normal_note(ui,note,Some(quote),true);
@@ -268,6 +418,15 @@ impl <F> MainUI<F>{
 					*lock=Some(note.id.clone());
 				}
 			}
+			if ui.button(&self.locale.reply).clicked(){
+				self.composer.lock().unwrap().set_reply(note);
+			}
+			if ui.button(&self.locale.quote).clicked(){
+				self.composer.lock().unwrap().set_quote(note);
+			}
+			if ui.button(&self.locale.thread).clicked(){
+				self.open_thread(note);
+			}
 			if ui.button(&self.locale.reload).clicked(){
 				let _=self.reload.blocking_send(load_misskey::LoadSrc::Note(note.id.clone()));
 			}
@@ -378,6 +537,9 @@ impl <F> MainUI<F>{
 					//Label::new(egui::RichText::new(instance.display_name()).color(Color32::from_gray(255)).background_color(instance.theme_color())).ui(ui);
 					ui.add_space(bt.rect.height());
 				}
+				if top_level&&(note.reply.is_some()||note.reply_id.is_some()){
+					self.reply_context(ui,note);
+				}
 				//トップレベル要素ならCWか確認する
 				let show_note=if !top_level{
 					true
@@ -402,6 +564,17 @@ impl <F> MainUI<F>{
 					//添付ファイル
 					let width=ui.available_width();
 					for file in &note.files{
+						//列や窓が広がったら大きく読み直す
+						let shown=if self.state.file_thumbnail_mode==crate::FileThumbnailMode::Original{
+							file.original_img.as_ref()
+						}else{
+							file.img.as_ref()
+						};
+						if let Some(shown)=shown{
+							if shown.request_width(width*ui.ctx().pixels_per_point()){
+								let _=self.delay_assets.blocking_send(data_model::DelayAssets::Image(shown.clone()));
+							}
+						}
 						let show_sensitive=file.show_sensitive.load(std::sync::atomic::Ordering::Relaxed);
 						let show_sensitive=!file.is_sensitive||show_sensitive||self.state.nsfw_always_show;
 						let img_opt=if !show_sensitive{
@@ -437,9 +610,8 @@ impl <F> MainUI<F>{
 										let preview=file.image(self.animate_frame).map(|v|Some(v)).unwrap_or_else(||{
 											file.blurhash.as_ref().map(|img|img.get(self.animate_frame)).unwrap_or_default()
 										});
-										if !original_img.loaded(){
-											let _=self.delay_assets.blocking_send(data_model::DelayAssets::Image(original_img.clone()));
-										}
+										let original_img=Arc::new(original_img.full_resolution());
+										let _=self.delay_assets.blocking_send(data_model::DelayAssets::Image(original_img.clone()));
 										*lock=Some(ZoomMediaView{
 											original_img,
 											preview,
@@ -478,6 +650,9 @@ impl <F> MainUI<F>{
 							img.ui(ui);
 						}
 					}
+					if let Some(poll)=note.poll.as_ref(){
+						self.poll_ui(ui,poll);
+					}
 					//URLサマリは本文外に描画
 					for (url,summaly) in note.text.urls(){
 						self.url_summaly(url,summaly,ui);
@@ -488,12 +663,14 @@ impl <F> MainUI<F>{
 					}
 				}
 				ui.horizontal_wrapped(|ui|{
-					for (emoji,count) in note.reactions.emojis.iter(){
+					for (emoji,count,reaction) in note.reactions.emojis.iter(){
 						let id=emoji.id_raw().id();
 						let img=emoji.image(self.animate_frame).unwrap_or_else(||self.dummy.get(self.animate_frame).unwrap());
 						let img=img.max_height(20f32);
 						let img=egui::widgets::Button::image_and_text(img, format!("{}",count));
-						let img=if id.contains("@"){
+						let mine=note.reactions.is_mine(reaction);
+						let img=img.selected(mine);
+						let img=if id.contains("@")&&!mine{
 							let img=img.frame(false);
 							let img=img.fill(Color32::from_black_alpha(0u8));
 							img.stroke(egui::Stroke::new(0f32,Color32::from_black_alpha(0u8)))
@@ -501,11 +678,19 @@ impl <F> MainUI<F>{
 							img
 						};
 						//ui.add_enabled(false,img).on_hover_text(emoji.id());
-						if img.ui(ui).on_hover_text(id.as_str()).clicked(){
+						let res=img.ui(ui).on_hover_text(id.as_str());
+						//長押しか右クリック、同じボタンにしばらくホバーするとリアクションしたユーザーを表示する
+						let hover_dwell=self.hover_dwell(ui,&res);
+						if res.long_touched()||res.secondary_clicked()||hover_dwell{
+							self.open_reaction_detail(note,reaction,emoji.image(self.animate_frame),*count,res.rect.left_bottom());
+						}
+						if res.clicked(){
 							tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async{
 								let _=self.delay_assets.send(DelayAssets::Note(note.clone())).await;
 							});
-							if let Some(emojis)=self.emojis.as_ref(){
+							if mine{
+								self.reaction_delete(note);
+							}else if let Some(emojis)=self.emojis.as_ref(){
 								let e=LocalEmojis::from_id(emoji.id_raw().to_owned(),emojis);
 								if let Some(e)=e{
 									self.reaction_send(note,&e);
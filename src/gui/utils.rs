@@ -3,15 +3,53 @@ use std::sync::Arc;
 use egui::{Color32, ScrollArea, Widget};
 use serde::{Deserialize, Serialize};
 
-use crate::{data_model::{DelayAssets, EmojiCache, LocalEmojis, Note, UrlImage, Visibility}, load_misskey};
+use crate::{data_model::{DelayAssets, EmojiCache, LocalEmojis, MFMClick, Note, UrlImage, Visibility}, drive, load_misskey};
 
-use super::main_ui::MainUI;
+use super::{composer::{target_note, Composer}, main_ui::MainUI};
 
 pub(super) struct ZoomMediaView{
 	pub(super) original_img:Arc<UrlImage>,
 	pub(super) preview:Option<egui::Image<'static>>,
 }
+#[derive(Clone,Debug,Deserialize)]
+pub(super) struct NamedItem{
+	pub(super) id:String,
+	pub(super) name:String,
+}
 impl <F> MainUI<F>{
+	pub(super) fn named_items(&self,endpoint:&str)->Vec<NamedItem>{
+		let build=self.client.post(format!("{}/api/{}",self.config.1.instance.as_ref().unwrap(),endpoint));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		#[derive(Debug,Serialize)]
+		struct ListPayload{
+			i:String,
+		}
+		let payload=ListPayload{
+			i:self.config.1.token.as_ref().unwrap().clone(),
+		};
+		let build=build.body(serde_json::to_string(&payload).unwrap());
+		tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+			let res=match build.send().await{
+				Ok(res)=>res,
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					return vec![];
+				}
+			};
+			println!("{} {}",endpoint,res.status());
+			match res.bytes().await.map(|b|serde_json::from_slice::<Vec<NamedItem>>(&b)){
+				Ok(Ok(items))=>items,
+				Ok(Err(e))=>{
+					eprintln!("{:?}",e);
+					vec![]
+				},
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					vec![]
+				}
+			}
+		})
+	}
 	pub(super) fn time_label(&self,ui:&mut egui::Ui,note:&Note){
 		let label=if note.visibility!=Visibility::Public{
 			let s=match note.visibility {
@@ -77,7 +115,161 @@ impl <F> MainUI<F>{
 		});
 		ok
 	}
-	pub(super) fn reaction_send(&self,note:&Note,emoji:&LocalEmojis)->bool{
+	pub(super) async fn user_id(&self,username:String,host:Option<String>)->Option<String>{
+		let instance=self.config.1.instance.as_ref().unwrap();
+		#[derive(Debug,Serialize,Deserialize)]
+		struct UserShowPayload{
+			username:String,
+			#[serde(skip_serializing_if = "Option::is_none")]
+			host:Option<String>,
+			i:String,
+		}
+		#[derive(Debug,Deserialize)]
+		struct IdResponse{
+			id:String,
+		}
+		//自サーバーのユーザーはhostを付けずに問い合わせる
+		let local_host=instance.split("://").last().unwrap_or(instance).trim_end_matches('/');
+		let host=host.filter(|h|h!=local_host);
+		let build=self.client.post(format!("{}/api/users/show",instance));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		let build=build.body(serde_json::to_string(&UserShowPayload{
+			username,
+			host,
+			i:self.config.1.token.as_ref().unwrap().clone(),
+		}).unwrap());
+		match build.send().await{
+			Ok(res)=>res.bytes().await.ok().and_then(|b|serde_json::from_slice::<IdResponse>(&b).ok()).map(|u|u.id),
+			Err(e)=>{
+				eprintln!("{:?}",e);
+				None
+			}
+		}
+	}
+	pub(super) fn open_mfm_click(&mut self,click:MFMClick){
+		let tl=match click{
+			MFMClick::Mention{username,host}=>{
+				let id=tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+					self.user_id(username,host).await
+				});
+				match id{
+					Some(id)=>load_misskey::TimeLine::User(id),
+					None=>return,
+				}
+			},
+			MFMClick::Hashtag(tag)=>load_misskey::TimeLine::Hashtag(tag),
+			MFMClick::Search(query)=>{
+				self.open_search(query);
+				return;
+			},
+		};
+		self.open_timeline.lock().unwrap().replace((Some(tl),None));
+	}
+	pub(super) fn note_send(&self,composer:&Composer)->Result<(),String>{
+		let instance=self.config.1.instance.as_ref().unwrap();
+		let token=self.config.1.token.as_ref().unwrap();
+		#[derive(Debug,Serialize,Deserialize)]
+		struct NoteCreatePayload{
+			#[serde(skip_serializing_if = "Option::is_none")]
+			text:Option<String>,
+			#[serde(skip_serializing_if = "Option::is_none")]
+			cw:Option<String>,
+			visibility:String,
+			#[serde(rename = "visibleUserIds")]
+			#[serde(skip_serializing_if = "Vec::is_empty")]
+			visible_user_ids:Vec<String>,
+			#[serde(rename = "localOnly")]
+			local_only:bool,
+			#[serde(rename = "replyId")]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			reply_id:Option<String>,
+			#[serde(rename = "renoteId")]
+			#[serde(skip_serializing_if = "Option::is_none")]
+			renote_id:Option<String>,
+			#[serde(rename = "fileIds")]
+			#[serde(skip_serializing_if = "Vec::is_empty")]
+			file_ids:Vec<String>,
+			i:String,
+		}
+		#[derive(Debug,Deserialize)]
+		struct IdResponse{
+			id:String,
+		}
+		#[derive(Debug,Deserialize)]
+		struct NoteCreateResponse{
+			#[serde(rename = "createdNote")]
+			created_note:IdResponse,
+		}
+		let mut payload=NoteCreatePayload{
+			text:if composer.text.is_empty(){
+				None
+			}else{
+				Some(composer.text.clone())
+			},
+			cw:composer.cw.clone().filter(|cw|!cw.is_empty()),
+			visibility:composer.visibility.to_string(),
+			visible_user_ids:vec![],
+			local_only:composer.local_only,
+			reply_id:composer.reply.as_ref().map(|n|n.id.clone()),
+			renote_id:composer.quote.as_ref().map(|n|n.id.clone()),
+			file_ids:vec![],
+			i:token.clone(),
+		};
+		tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+			if composer.visibility==Visibility::Specified{
+				//@user@host形式の宛先をユーザーIDに変換する
+				for acct in composer.visible_users.split(|c:char|c.is_whitespace()||c==','){
+					let mut acct=acct.trim_start_matches('@').splitn(2,'@');
+					let username=match acct.next(){
+						Some(s) if !s.is_empty()=>s.to_owned(),
+						_=>continue,
+					};
+					let host=acct.next().map(|s|s.to_owned());
+					let name=match host.as_ref(){
+						Some(host)=>format!("@{}@{}",username,host),
+						None=>format!("@{}",username),
+					};
+					match self.user_id(username,host).await{
+						Some(id)=>payload.visible_user_ids.push(id),
+						None=>return Err(format!("get api/users/show error {}",name)),
+					}
+				}
+			}
+			for file in &composer.files{
+				match drive::update_file(&self.client,&self.config.1,file).await{
+					Ok(id)=>payload.file_ids.push(id),
+					Err(e)=>{
+						eprintln!("{}",e);
+						return Err(e);
+					}
+				}
+			}
+			println!("ノート送信 {:?}",payload.visibility);
+			let build=self.client.post(format!("{}/api/notes/create",instance));
+			let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+			let build=build.body(serde_json::to_string(&payload).unwrap());
+			let res=build.send().await;
+			match res{
+				Ok(res)=>{
+					let status=res.status().as_u16();
+					println!("NoteSendStatus {}",status);
+					let body=res.bytes().await.unwrap_or_default();
+					if status!=200{
+						return Err(format!("post api/notes/create error {} {}",status,String::from_utf8_lossy(&body)));
+					}
+					if let Ok(res)=serde_json::from_slice::<NoteCreateResponse>(&body){
+						let _=self.reload.send(load_misskey::LoadSrc::Note(res.created_note.id)).await;
+					}
+					Ok(())
+				},
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					Err(format!("post api/notes/create error {}",e))
+				}
+			}
+		})
+	}
+	pub(super) fn reaction_send(&self,note:&Arc<Note>,emoji:&LocalEmojis)->bool{
 		let build=self.client.post(format!("{}/api/notes/reactions/create",self.config.1.instance.as_ref().unwrap()));
 		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
 		#[derive(Debug,Serialize,Deserialize)]
@@ -87,16 +279,16 @@ impl <F> MainUI<F>{
 			reaction:String,
 			i:String,
 		}
-		let id=if note.text.raw.is_empty(){
-			match note.quote.as_ref(){
-				Some(n)=>n.id.clone(),
-				None=>return false,
+		//単純なリノートはリノート元にリアクションする
+		let target=target_note(note);
+		//既にリアクションしていれば付け替える
+		if target.reactions.my_reaction.is_some(){
+			if !self.reaction_delete(note){
+				return false;
 			}
-		}else{
-			note.id.clone()
-		};
+		}
 		let payload=ReactionCreatepayload{
-			note_id:id,
+			note_id:target.id.clone(),
 			reaction:emoji.reaction(),
 			i:self.config.1.token.as_ref().unwrap().clone(),
 		};
@@ -122,7 +314,44 @@ impl <F> MainUI<F>{
 		});
 		ok
 	}
-	pub(super) fn reaction_picker(&self,ui:&mut egui::Ui,emojis:&EmojiCache,note:&Note){
+	pub(super) fn reaction_delete(&self,note:&Arc<Note>)->bool{
+		let build=self.client.post(format!("{}/api/notes/reactions/delete",self.config.1.instance.as_ref().unwrap()));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		#[derive(Debug,Serialize,Deserialize)]
+		struct ReactionDeletePayload{
+			#[serde(rename = "noteId")]
+			note_id:String,
+			i:String,
+		}
+		//単純なリノートはリノート元にリアクションする
+		let target=target_note(note);
+		let payload=ReactionDeletePayload{
+			note_id:target.id.clone(),
+			i:self.config.1.token.as_ref().unwrap().clone(),
+		};
+		println!("リアクション削除 {:?}",payload);
+		let build=build.body(serde_json::to_string(&payload).unwrap());
+		let ok=tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async{
+			let res=build.send().await;
+			match res{
+				Ok(res)=>{
+					let status=res.status().as_u16();
+					println!("ReactionDeleteStatus {}",status);
+					let ok=status==204;
+					if ok{
+						let _=self.reload.send(load_misskey::LoadSrc::Note(note.id.clone())).await;
+					}
+					ok
+				},
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					false
+				}
+			}
+		});
+		ok
+	}
+	pub(super) fn reaction_picker(&self,ui:&mut egui::Ui,emojis:&EmojiCache,note:&Arc<Note>){
 		let emoji_size=25f32;
 		let width=ui.available_width();
 		let horizontal_count=(width/(emoji_size+15f32)-0.5).round() as usize;
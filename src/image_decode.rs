@@ -0,0 +1,195 @@
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+
+use image::{AnimationDecoder, DynamicImage};
+
+//表示幅が分かるまでの添付ファイルの幅の上限(物理ピクセル)
+pub const DEFAULT_MEDIA_WIDTH_PX:u32=1024;
+static DECODE_POOL:OnceLock<DecodePool>=OnceLock::new();
+//絵文字とアイコンは表示サイズが小さいので固定の上限にする
+const EMOJI_MAX_PX:u32=128;
+const ICON_MAX_PX:u32=192;
+//アニメーションは縮小後の総ピクセル数とフレーム数で打ち切る
+const MAX_ANIMATION_PIXELS:u64=32*1024*1024;
+const MAX_ANIMATION_FRAMES:usize=500;
+//画像の用途ごとの縮小の上限
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ImageSize{
+	Emoji,
+	Icon,
+	//添付ファイルなどノートの幅で表示するもの
+	Media,
+	//拡大表示用に縮小しない
+	Original,
+}
+impl ImageSize{
+	//幅と高さの上限。添付ファイルは表示幅に合わせる
+	fn max_px(self,media_width:u32)->Option<(u32,u32)>{
+		match self{
+			ImageSize::Emoji=>Some((EMOJI_MAX_PX,EMOJI_MAX_PX)),
+			ImageSize::Icon=>Some((ICON_MAX_PX,ICON_MAX_PX)),
+			//縦長の画像も幅いっぱいに表示するので幅だけ合わせる
+			ImageSize::Media=>Some((media_width,u32::MAX)),
+			ImageSize::Original=>None,
+		}
+	}
+}
+type DecodeJob=Box<dyn FnOnce()+Send>;
+//UIスレッドやローダーを止めないためのデコード専用スレッド
+struct DecodePool{
+	send:Mutex<mpsc::Sender<DecodeJob>>,
+}
+impl DecodePool{
+	fn new()->Self{
+		let (send,recv)=mpsc::channel::<DecodeJob>();
+		let recv=Arc::new(Mutex::new(recv));
+		let threads=std::thread::available_parallelism().map(|n|n.get()).unwrap_or(2).clamp(1,4);
+		for i in 0..threads{
+			let recv=recv.clone();
+			let res=std::thread::Builder::new().name(format!("image-decode-{}",i)).spawn(move||{
+				loop{
+					let job=recv.lock().unwrap().recv();
+					match job{
+						Ok(job)=>job(),
+						Err(_)=>break,
+					}
+				}
+			});
+			if let Err(e)=res{
+				eprintln!("{:?}",e);
+			}
+		}
+		Self{
+			send:Mutex::new(send),
+		}
+	}
+}
+//用途に合わせて縮小する
+pub(crate) async fn decode(buf:Vec<u8>,size:ImageSize,media_width:u32)->Result<Vec<(u32,egui::ColorImage)>,String>{
+	let (send,recv)=tokio::sync::oneshot::channel();
+	let max=size.max_px(media_width);
+	let job:DecodeJob=Box::new(move||{
+		let _=send.send(decode_blocking(&buf,max));
+	});
+	DECODE_POOL.get_or_init(DecodePool::new).send.lock().unwrap().send(job).map_err(|e|e.to_string())?;
+	recv.await.map_err(|e|e.to_string())?
+}
+fn decode_blocking(buf:&[u8],max:Option<(u32,u32)>)->Result<Vec<(u32,egui::ColorImage)>,String>{
+	let codec=image::guess_format(buf).map_err(|e|format!("{:?}",e))?;
+	let mut frames=None;
+	match codec{
+		image::ImageFormat::Gif=>{
+			match image::codecs::gif::GifDecoder::new(std::io::Cursor::new(buf)){
+				Ok(decoder)=>frames=animation_frames(decoder.into_frames(),max,true),
+				Err(e)=>eprintln!("{:?}",e),
+			}
+		},
+		image::ImageFormat::Png=>{
+			match image::codecs::png::PngDecoder::new(std::io::Cursor::new(buf)){
+				Ok(decoder) if decoder.is_apng().unwrap_or(false)=>{
+					match decoder.apng(){
+						Ok(decoder)=>frames=animation_frames(decoder.into_frames(),max,false),
+						Err(e)=>eprintln!("{:?}",e),
+					}
+				},
+				Ok(_)=>{},
+				Err(e)=>eprintln!("{:?}",e),
+			}
+		},
+		_=>{},
+	}
+	if let image::ImageFormat::WebP=codec{
+		let decoder=webp::AnimDecoder::new(buf);
+		match decoder.decode(){
+			Ok(image)=>{
+				let mut limit=AnimationLimit::default();
+				frames=Some(image.into_iter().map_while(|frame|{
+					let img=downscale(Into::<DynamicImage>::into(&frame),max);
+					limit.push(&img).then(||(frame.get_time_ms() as u32,img))
+				}).collect::<Vec<_>>());
+			},
+			Err(e)=>{
+				eprintln!("{:?}",e);
+			},
+		}
+	}
+	let frames=match frames{
+		Some(frames)=>frames,
+		None=>{
+			let img=image::load_from_memory_with_format(buf,codec).map_err(|e|format!("{} {:?}",codec.to_mime_type(),e))?;
+			vec![(0,downscale(img,max))]
+		},
+	};
+	Ok(frames.into_iter().map(|(timestamp,img)|(timestamp,to_color_image(img))).collect())
+}
+#[derive(Default)]
+struct AnimationLimit{
+	frames:usize,
+	pixels:u64,
+}
+impl AnimationLimit{
+	//上限に達したらfalse
+	fn push(&mut self,img:&DynamicImage)->bool{
+		self.frames+=1;
+		self.pixels+=img.width() as u64*img.height() as u64;
+		self.frames<=MAX_ANIMATION_FRAMES&&self.pixels<=MAX_ANIMATION_PIXELS
+	}
+}
+//WebPと同じく各フレームの終了時刻を付ける。フレームは読んだ端から縮小する
+fn animation_frames(frames:image::Frames,max:Option<(u32,u32)>,is_gif:bool)->Option<Vec<(u32,DynamicImage)>>{
+	let mut timestamp=0u32;
+	let mut list=vec![];
+	let mut limit=AnimationLimit::default();
+	for frame in frames{
+		let frame=match frame{
+			Ok(frame)=>frame,
+			Err(e)=>{
+				eprintln!("{:?}",e);
+				break;
+			}
+		};
+		let (numer,denom)=frame.delay().numer_denom_ms();
+		let delay=if denom==0{
+			0
+		}else{
+			numer/denom
+		};
+		//ブラウザと同じくGIFの極端に短い間隔は100msとして扱う
+		let delay=if is_gif&&delay<=10{
+			100
+		}else{
+			delay
+		};
+		timestamp=timestamp.saturating_add(delay);
+		let img=downscale(DynamicImage::ImageRgba8(frame.into_buffer()),max);
+		if !limit.push(&img){
+			println!("animation truncated at {} frames",list.len());
+			break;
+		}
+		list.push((timestamp,img));
+	}
+	//1フレームだけなら静止画と同じ扱い
+	if list.len()<2{
+		return None;
+	}
+	Some(list)
+}
+fn downscale(img:DynamicImage,max:Option<(u32,u32)>)->DynamicImage{
+	let (max_width,max_height)=match max{
+		Some(max)=>max,
+		None=>return img,
+	};
+	if img.width()>max_width||img.height()>max_height{
+		img.resize(max_width,max_height,image::imageops::FilterType::Triangle)
+	}else{
+		img
+	}
+}
+pub(crate) fn to_color_image(img:DynamicImage)->egui::ColorImage{
+	let size=[img.width() as _, img.height() as _];
+	let image_buffer = img.into_rgba8();
+	let pixels = image_buffer.as_flat_samples();
+	egui::ColorImage::from_rgba_unmultiplied(
+		size,
+		pixels.as_slice(),
+	)
+}
@@ -4,6 +4,12 @@ mod data_model;
 mod load_misskey;
 mod gui;
 mod delay_assets;
+mod drive;
+mod mfm;
+mod miauth;
+mod note_store;
+mod disk_cache;
+mod image_decode;
 use std::{io::Write, sync::Arc};
 
 use data_model::Visibility;
@@ -36,9 +42,30 @@ pub struct StateFile{
 	file_thumbnail_mode:FileThumbnailMode,
 	default_renote_visibility:Visibility,
 	page_notes:u8,
+	#[serde(default)]
+	deck_mode:bool,
+	//デッキの列の並び
+	#[serde(default)]
+	deck:Vec<load_misskey::TimeLine>,
+	//画像キャッシュの容量(MB)
+	#[serde(default)]
+	cache_size_mb:Option<u32>,
+	//アカウント毎に別のファイルへ保存する
+	#[serde(skip)]
+	account:Option<String>,
 }
 impl StateFile{
-	fn file()->String{
+	fn file(&self)->String{
+		let base=Self::base_file();
+		match self.account.as_ref(){
+			Some(key)=>match base.strip_suffix(".json"){
+				Some(stem)=>format!("{}_{}.json",stem,key),
+				None=>format!("{}_{}",base,key),
+			},
+			None=>base,
+		}
+	}
+	fn base_file()->String{
 		match std::env::var("YAC_STATE_PATH"){
 			Ok(path)=>{
 				if path.is_empty(){
@@ -51,7 +78,7 @@ impl StateFile{
 		}
 	}
 	pub fn write(&self,delay_assets:&tokio::sync::mpsc::Sender<data_model::DelayAssets>){
-		if let Ok(writer)=std::fs::File::create(Self::file()){
+		if let Ok(writer)=std::fs::File::create(self.file()){
 			if let Err(e)=serde_json::to_writer(writer,&self){
 				eprintln!("{:?}",e);
 			}
@@ -60,7 +87,10 @@ impl StateFile{
 		let _=delay_assets.blocking_send(data_model::DelayAssets::UpdateState(v));
 	}
 	pub fn load()->Option<Self>{
-		if let Ok(writer)=std::fs::File::open(Self::file()){
+		Self::load_from(&Self::base_file())
+	}
+	fn load_from(path:&str)->Option<Self>{
+		if let Ok(writer)=std::fs::File::open(path){
 			match serde_json::from_reader(writer){
 				Ok(d)=>return Some(d),
 				Err(e)=>eprintln!("{:?}",e)
@@ -68,6 +98,29 @@ impl StateFile{
 		}
 		None
 	}
+	//無ければ共通のファイルを引き継ぐ
+	pub fn load_account(account:Option<String>)->Self{
+		let path=Self{
+			account:account.clone(),
+			..Default::default()
+		}.file();
+		let mut state=Self::load_from(&path).unwrap_or_else(||{
+			//タイムラインやデッキはアカウント毎なので表示設定だけ引き継ぐ
+			let base=Self::load().unwrap_or_default();
+			Self{
+				nsfw_always_show:base.nsfw_always_show,
+				auto_old_timeline:base.auto_old_timeline,
+				file_thumbnail_mode:base.file_thumbnail_mode,
+				default_renote_visibility:base.default_renote_visibility,
+				page_notes:base.page_notes,
+				deck_mode:base.deck_mode,
+				cache_size_mb:base.cache_size_mb,
+				..Default::default()
+			}
+		});
+		state.account=account;
+		state
+	}
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct ConfigFile{
@@ -75,6 +128,96 @@ pub struct ConfigFile{
 	instance:Option<String>,
 	is_animation:Option<bool>,
 	top:Option<u32>,
+	#[serde(default)]
+	accounts:Vec<Account>,
+}
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct Account{
+	instance:String,
+	token:String,
+	user_id:Option<String>,
+	username:Option<String>,
+	avatar_url:Option<String>,
+}
+impl Account{
+	fn key(&self)->String{
+		let id=self.user_id.as_ref().unwrap_or(&self.token);
+		uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_URL,format!("{}/{}",self.instance,id).as_bytes()).simple().to_string()
+	}
+	fn label(&self)->String{
+		let host=reqwest::Url::parse(&self.instance).ok().and_then(|url|url.host_str().map(|h|h.to_owned())).unwrap_or_else(||self.instance.clone());
+		match self.username.as_ref(){
+			Some(username)=>format!("@{}@{}",username,host),
+			None=>host,
+		}
+	}
+	fn is_same(&self,other:&Account)->bool{
+		self.instance==other.instance&&match (&self.user_id,&other.user_id){
+			(Some(a),Some(b))=>a==b,
+			_=>self.token==other.token,
+		}
+	}
+}
+impl ConfigFile{
+	fn current_account(&self)->Option<Account>{
+		let instance=self.instance.as_ref()?;
+		let token=self.token.as_ref()?;
+		let hit=self.accounts.iter().find(|a|&a.instance==instance&&&a.token==token);
+		Some(hit.cloned().unwrap_or_else(||Account{
+			instance:instance.clone(),
+			token:token.clone(),
+			user_id:None,
+			username:None,
+			avatar_url:None,
+		}))
+	}
+	//旧形式の設定は現在のトークンだけをアカウントとして扱う
+	fn accounts(&self)->Vec<Account>{
+		let mut accounts=self.accounts.clone();
+		if let Some(current)=self.current_account(){
+			if !accounts.iter().any(|a|a.is_same(&current)){
+				accounts.push(current);
+			}
+		}
+		accounts
+	}
+	fn account_key(&self)->Option<String>{
+		self.current_account().map(|a|a.key())
+	}
+	//アカウントを追加または切り替える
+	fn login(&self,account:Account)->Self{
+		let mut accounts=self.accounts();
+		match accounts.iter_mut().find(|a|a.is_same(&account)){
+			Some(old)=>*old=account.clone(),
+			None=>accounts.push(account.clone()),
+		}
+		Self{
+			token:Some(account.token),
+			instance:Some(account.instance),
+			is_animation:self.is_animation,
+			top:self.top,
+			accounts,
+		}
+	}
+	//問い合わせたユーザー情報で保存済みのアカウントを更新する
+	fn update_account(&self,account:Account)->Self{
+		let mut accounts=self.accounts();
+		match accounts.iter_mut().find(|a|a.is_same(&account)){
+			Some(old)=>*old=account,
+			None=>accounts.push(account),
+		}
+		Self{
+			token:self.token.clone(),
+			instance:self.instance.clone(),
+			is_animation:self.is_animation,
+			top:self.top,
+			accounts,
+		}
+	}
+	fn write(&self,path:&str)->std::io::Result<()>{
+		let config=serde_json::to_string_pretty(self).unwrap();
+		std::fs::File::create(path)?.write_all(config.as_bytes())
+	}
 }
 #[derive(Debug,Serialize,Deserialize)]
 pub struct LocaleFile{
@@ -107,6 +250,79 @@ pub struct LocaleFile{
 	send_renote:String,
 	default_renote_visibility:String,
 	page_notes_count:String,
+	note_post:String,
+	note_placeholder:String,
+	cw_enabled:String,
+	cw_placeholder:String,
+	local_only:String,
+	visible_users:String,
+	reply:String,
+	quote:String,
+	reply_to:String,
+	quote_to:String,
+	cancel:String,
+	attach_file:String,
+	file_path:String,
+	sensitive:String,
+	file_comment:String,
+	remove:String,
+	lists:String,
+	antennas:String,
+	notifications:String,
+	notification_reaction:String,
+	notification_quote:String,
+	notification_reply:String,
+	notification_mention:String,
+	notification_follow:String,
+	notification_follow_request:String,
+	notification_follow_accepted:String,
+	load_more:String,
+	thread:String,
+	close:String,
+	search:String,
+	search_keyword:String,
+	search_user:String,
+	search_host:String,
+	user_not_found:String,
+	notes_count:String,
+	following_count:String,
+	followers_count:String,
+	joined_at:String,
+	pinned_notes:String,
+	profile:String,
+	follow:String,
+	unfollow:String,
+	follow_requested:String,
+	followed_by:String,
+	mute:String,
+	unmute:String,
+	block:String,
+	unblock:String,
+	follow_requests:String,
+	accept:String,
+	reject:String,
+	stream_disconnected:String,
+	stream_connecting:String,
+	stream_connected:String,
+	stream_reconnecting:String,
+	stream_failed:String,
+	reconnect:String,
+	login:String,
+	login_instance:String,
+	login_waiting:String,
+	login_open_browser:String,
+	login_invalid_instance:String,
+	login_failed:String,
+	add_account:String,
+	deck_mode:String,
+	deck_empty:String,
+	image_cache:String,
+	cache_files:String,
+	clear_cache:String,
+	debug_overlay:String,
+	poll_votes:String,
+	poll_closed:String,
+	block_confirm:String,
 }
 fn load_config()->(String,Arc<ConfigFile>){
 	let config_path=match std::env::var("YAC_CONFIG_PATH"){
@@ -125,6 +341,7 @@ fn load_config()->(String,Arc<ConfigFile>){
 			instance:None,
 			is_animation:Some(data_model::DEFAULT_ANIMATION),
 			top:Some(0u32),
+			accounts:vec![],
 		};
 		let default_config=serde_json::to_string_pretty(&default_config).unwrap();
 		std::fs::File::create(&config_path).expect("create default config.json").write_all(default_config.as_bytes()).unwrap();
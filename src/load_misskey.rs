@@ -1,31 +1,77 @@
 
-use std::{collections::HashMap, hash::{Hash, Hasher}, sync::{atomic::{AtomicBool, AtomicU32}, Arc}};
+use std::{collections::{HashMap, HashSet}, hash::{Hash, Hasher}, sync::{atomic::{AtomicBool, AtomicU32, AtomicU64}, Arc}};
 
 use futures::{ SinkExt, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc::{Receiver, Sender}, Mutex};
 
-use crate::{data_model::{self, DelayAssets, EmojiCache, NoteFile}, ConfigFile};
+use crate::{data_model::{self, DelayAssets, EmojiCache, NoteFile}, note_store::NoteStore, Account, ConfigFile};
 
 pub struct TLOption{
 	pub(crate) until_id:Option<String>,
+	pub(crate) since_id:Option<String>,
 	pub(crate) limit:u8,
 	pub(crate) tl:TimeLine,
 	pub(crate) known_notes:Vec<Arc<data_model::Note>>,
 	pub(crate) websocket:bool,
 }
+pub struct SearchOption{
+	pub(crate) query:String,
+	pub(crate) user_id:Option<String>,
+	pub(crate) host:Option<String>,
+	pub(crate) until_id:Option<String>,
+}
 pub enum LoadSrc{
 	TimeLine(TLOption),
 	Note(String),
+	Notifications(Option<String>),
+	Thread(String),
+	Search(SearchOption),
+	//ノートID,リアクション,untilId
+	Reactions(String,String,Option<String>),
+	//デッキの列ID
+	Column(u32,TLOption),
+	CloseColumn(u32),
+}
+//表示中のノートの購読
+pub enum NoteCapture{
+	Sub(String),
+	Unsub(String),
+}
+//ストリーミングの接続状態
+#[derive(Clone,Debug,Default)]
+pub enum StreamState{
+	#[default]
+	Disconnected,
+	Connecting,
+	Connected,
+	Reconnecting,
+	Failed(String),
+}
+//購読したノートの変更
+pub enum NoteUpdate{
+	Updated(Arc<data_model::Note>),
+	Deleted(String),
 }
 pub async fn load_misskey(
 	config:Arc<ConfigFile>,
 	note_ui:Sender<Arc<data_model::Note>>,
 	delay_assets:Sender<DelayAssets>,
 	client:Client,
-	mut reload_event:Receiver<LoadSrc>,
+	reload_event:Arc<Mutex<Receiver<LoadSrc>>>,
 	emojis_send:Sender<EmojiCache>,
+	meta_send:Sender<Arc<ApiMeta>>,
+	notification_ui:Sender<(Arc<data_model::Notification>,bool)>,
+	thread_ui:Sender<Result<data_model::NoteThread,(String,String)>>,
+	search_ui:Sender<Result<(Vec<Arc<data_model::Note>>,bool),String>>,
+	user_ui:Sender<data_model::UserDetail>,
+	reaction_ui:Sender<data_model::ReactionUsers>,
+	capture_event:Arc<Mutex<Receiver<NoteCapture>>>,
+	note_update_ui:Sender<NoteUpdate>,
+	stream_state_ui:tokio::sync::watch::Sender<StreamState>,
+	deck_ui:Sender<(u32,Arc<data_model::Note>)>,
+	account_ui:Sender<Account>,
 ){
 	if config.token.as_ref().is_none(){
 		let mes=format!("token が指定されていません");
@@ -42,25 +88,56 @@ pub async fn load_misskey(
 		return;
 	}
 	let local_instance=config.instance.clone().unwrap();
-	let meta=meta(&client,&local_instance).await;
-	if let Err(e)=meta{
-		let mes=format!("get api/meta error {}",e);
-		if let Err(e)=note_ui.send(Arc::new(data_model::Note::system_message(mes,"").await)).await{
-			eprintln!("{:?}",e);
+	let store=Arc::new(NoteStore::new(config.account_key()));
+	//前回の内容があればそれで始めて裏で取り直す
+	let (meta,local_emojis,stored)=match store.meta().await{
+		Some((meta,local_emojis))=>(meta,local_emojis,true),
+		None=>{
+			let meta=match meta(&client,&local_instance).await{
+				Ok(meta)=>meta,
+				Err(e)=>{
+					let mes=format!("get api/meta error {}",e);
+					if let Err(e)=note_ui.send(Arc::new(data_model::Note::system_message(mes,"").await)).await{
+						eprintln!("{:?}",e);
+					}
+					return;
+				}
+			};
+			let local_emojis=local_emojis(&client,&local_instance).await.unwrap_or_default();
+			store.save_meta(&meta,&local_emojis).await;
+			(meta,local_emojis,false)
 		}
-		return;
+	};
+	let meta=Arc::new(meta);
+	let _=meta_send.send(meta.clone()).await;
+	//自分のリアクションを判別するため
+	//保存したノートを先に出せるように問い合わせは裏で行う
+	let account=config.current_account().unwrap();
+	let my_id=Arc::new(std::sync::Mutex::new(account.user_id.clone()));
+	//旧形式の設定のアカウントは名前とアイコンも無い
+	if account.user_id.is_none()||account.username.is_none(){
+		let client=client.clone();
+		let my_id=my_id.clone();
+		tokio::runtime::Handle::current().spawn(async move{
+			match i(&client,&account.instance,account.token.clone()).await{
+				Ok(user)=>{
+					*my_id.lock().unwrap()=Some(user.id.clone());
+					//次回から問い合わせないように保存してもらう
+					let _=account_ui.send(Account{
+						user_id:Some(user.id),
+						username:Some(user.username),
+						avatar_url:user.avatar_url,
+						..account
+					}).await;
+				},
+				Err(e)=>eprintln!("get api/i error {}",e),
+			}
+		});
 	}
-	let meta=meta.unwrap();
-	let media_proxy=meta.media_proxy;
+	let media_proxy=meta.media_proxy.clone();
 	println!("media_proxy:{}",media_proxy);
-	let mut local_emojis=HashMap::new();
-	if let Ok(emojis)=emojis(&client,&local_instance).await{
-		for emoji in emojis.emojis{
-			local_emojis.insert(emoji.name,emoji.url);
-		}
-	}
 	println!("{} local emojis",local_emojis.len());
-	let emoji_cache=data_model::EmojiCache::new(media_proxy,&local_instance,Arc::new(local_emojis));
+	let mut emoji_cache=data_model::EmojiCache::new(media_proxy,&local_instance,Arc::new(local_emojis));
 	let _=emojis_send.send(emoji_cache.clone()).await;
 	let mut instance_cache=HashMap::new();
 	let mut user_cache=HashMap::new();
@@ -68,26 +145,113 @@ pub async fn load_misskey(
 	let mut note_cache: HashMap<String, Arc<data_model::Note>>=HashMap::new();
 	let (raw_note_sender,mut raw_note_receiver)=tokio::sync::mpsc::channel(4);
 	let note_ui0=note_ui.clone();
+	let search_ui0=search_ui.clone();
+	let reaction_ui0=reaction_ui.clone();
+	let thread_ui0=thread_ui.clone();
+	if stored{
+		let client=client.clone();
+		let store=store.clone();
+		let sender=raw_note_sender.clone();
+		tokio::runtime::Handle::current().spawn(async move{
+			let meta=match self::meta(&client,&local_instance).await{
+				Ok(meta)=>meta,
+				Err(e)=>{
+					eprintln!("get api/meta error {}",e);
+					return;
+				}
+			};
+			let local_emojis=match self::local_emojis(&client,&local_instance).await{
+				Ok(local_emojis)=>local_emojis,
+				Err(e)=>{
+					eprintln!("get api/emojis error {}",e);
+					return;
+				}
+			};
+			store.save_meta(&meta,&local_emojis).await;
+			let emoji_cache=data_model::EmojiCache::new(meta.media_proxy.clone(),&local_instance,Arc::new(local_emojis));
+			let _=meta_send.send(Arc::new(meta)).await;
+			let _=emojis_send.send(emoji_cache.clone()).await;
+			let _=sender.send(RawNotes::Emojis(emoji_cache)).await;
+		});
+	}
+	let ws_state=Arc::new(Mutex::new(WSState{
+		stream: None,
+		now_stream: None,
+		main_stream: None,
+		captured: HashMap::new(),
+		since_id: None,
+		columns: HashMap::new(),
+		store: store.clone(),
+		client: client.clone(),
+		state_ui: stream_state_ui,
+	}));
+	let ws_state0=ws_state.clone();
 	tokio::runtime::Handle::current().spawn(async move{
-		let mut state=WSState{
-			stream: None,
-			now_stream: None,
-		};
-		while let Some(limit) = reload_event.recv().await{
+		loop{
+			let capture=match capture_event.lock().await.recv().await{
+				Some(capture)=>capture,
+				None=>break,
+			};
+			if let Err(e)=ws_state0.lock().await.capture(capture).await{
+				eprintln!("capture error {:?}",e);
+			}
+		}
+	});
+	let store0=store.clone();
+	tokio::runtime::Handle::current().spawn(async move{
+		let store=store0;
+		loop{
+			//アカウントを切り替えると次のローダーが引き継ぐ
+			let limit=match reload_event.lock().await.recv().await{
+				Some(limit)=>limit,
+				None=>break,
+			};
 			match limit{
 				LoadSrc::TimeLine(limit) => {
 					let tl=limit.tl.clone();
-					eprintln!("read_websocket {:?}",read_websocket(config.clone(),raw_note_sender.clone(),if limit.websocket{
+					let head=limit.until_id.is_none();
+					//保存してあるノートを先に出す
+					if head{
+						let notes=store.notes(&tl).await;
+						if !notes.is_empty(){
+							if let Err(e)=raw_note_sender.send(RawNotes::Array(notes)).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					}
+					//ユーザーTLの先頭を開いた時はプロフィールも取得する
+					let user_detail=match (&tl,&limit.until_id){
+						(TimeLine::User(id),None)=>Some(id.clone()),
+						_=>None,
+					};
+					let mut state=ws_state.lock().await;
+					let res=read_websocket(config.clone(),raw_note_sender.clone(),if limit.websocket{
 						if let TimeLine::User(_)=&tl{
 							//ユーザーTLにはWebSocketが無い
 							None
 						}else{
-							Some(tl.clone().into())
+							Some((tl.clone(),limit.limit))
 						}
 					}else{
 						None
-					},&mut state).await);
+					},&mut state).await;
+					eprintln!("read_websocket {:?}",res);
+					if let Err(e)=res{
+						let _=state.state_ui.send(StreamState::Failed(e.to_string()));
+					}
+					drop(state);
+					//取りこぼし補完の起点にする
+					let since_id=if limit.websocket&&limit.until_id.is_none(){
+						ws_state.lock().await.since_id.clone()
+					}else{
+						None
+					};
 					let htl=read_timeline(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),limit).await;
+					if let (Ok(notes),Some(since_id))=(htl.as_ref(),since_id.as_ref()){
+						for note in notes{
+							update_since_id(since_id,&note.id);
+						}
+					}
 					if let Err(e)=htl{
 						let mes=format!("get api/notes/{} error {}",tl.to_string(),e);
 						if let Err(e)=note_ui0.send(Arc::new(data_model::Note::system_message(mes,"").await)).await{
@@ -96,10 +260,74 @@ pub async fn load_misskey(
 					}else{
 						let notes=htl.unwrap();
 						println!("{} notes get",notes.len());
+						if head{
+							store.save_notes(&tl,&notes);
+						}
 						if let Err(e)=raw_note_sender.send(RawNotes::Array(notes)).await{
 							eprintln!("{:?}",e);
 						}
 					}
+					if let Some(id)=user_detail{
+						match read_user_detail(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),id).await{
+							Ok(user)=>{
+								if let Err(e)=raw_note_sender.send(RawNotes::UserDetail(Box::new(user))).await{
+									eprintln!("{:?}",e);
+								}
+							},
+							Err(e)=>{
+								let mes=format!("get api/users/show error {}",e);
+								let _=note_ui0.send(Arc::new(data_model::Note::system_message(mes,"").await)).await;
+							}
+						}
+					}
+				},
+				LoadSrc::Column(column,opt) => {
+					let tl=opt.tl.clone();
+					let head=opt.until_id.is_none();
+					if head{
+						let notes=store.notes(&tl).await;
+						if !notes.is_empty(){
+							if let Err(e)=raw_note_sender.send(RawNotes::Column(column,notes)).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					}
+					//ユーザーTLにはWebSocketが無い
+					let since_id=if opt.websocket&&opt.until_id.is_none()&&!matches!(tl,TimeLine::User(_)){
+						let mut state=ws_state.lock().await;
+						match open_column(config.clone(),raw_note_sender.clone(),column,(tl.clone(),opt.limit),&mut state).await{
+							Ok(since_id)=>since_id,
+							Err(e)=>{
+								eprintln!("open_column {:?}",e);
+								let _=state.state_ui.send(StreamState::Failed(e.to_string()));
+								None
+							}
+						}
+					}else{
+						None
+					};
+					match read_timeline(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),opt).await{
+						Ok(notes)=>{
+							if let Some(since_id)=since_id.as_ref(){
+								for note in &notes{
+									update_since_id(since_id,&note.id);
+								}
+							}
+							if head{
+								store.save_notes(&tl,&notes);
+							}
+							if let Err(e)=raw_note_sender.send(RawNotes::Column(column,notes)).await{
+								eprintln!("{:?}",e);
+							}
+						},
+						Err(e)=>{
+							let mes=format!("get api/notes/{} error {}",tl.to_string(),e);
+							let _=note_ui0.send(Arc::new(data_model::Note::system_message(mes,"").await)).await;
+						}
+					}
+				},
+				LoadSrc::CloseColumn(column) => {
+					close_column(column,&mut *ws_state.lock().await).await;
 				},
 				LoadSrc::Note(note_id) => {
 					let note=client.post(format!("{}/api/notes/show",config.instance.as_ref().unwrap()));
@@ -131,6 +359,74 @@ pub async fn load_misskey(
 						},
 					}
 				},
+				LoadSrc::Notifications(until_id) => {
+					match read_notifications(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),until_id).await{
+						Ok(notifications)=>{
+							println!("{} notifications get",notifications.len());
+							if let Err(e)=raw_note_sender.send(RawNotes::Notifications(notifications,false)).await{
+								eprintln!("{:?}",e);
+							}
+						},
+						Err(e)=>{
+							let mes=format!("get api/i/notifications error {}",e);
+							let _=note_ui0.send(Arc::new(data_model::Note::system_message(mes,"").await)).await;
+						}
+					}
+				},
+				LoadSrc::Thread(note_id) => {
+					match read_thread(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),note_id.clone()).await{
+						Ok(thread)=>{
+							if let Err(e)=raw_note_sender.send(RawNotes::Thread(thread)).await{
+								eprintln!("{:?}",e);
+							}
+						},
+						Err(e)=>{
+							//スレッド画面に失敗を伝える
+							if let Err(e)=thread_ui0.send(Err((note_id,format!("get api/notes/conversation error {}",e)))).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					}
+				},
+				LoadSrc::Reactions(note_id,reaction,until_id) => {
+					let is_next=until_id.is_some();
+					match read_reactions(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),&note_id,&reaction,until_id).await{
+						Ok(list)=>{
+							if let Err(e)=raw_note_sender.send(RawNotes::Reactions(note_id,reaction,list,is_next)).await{
+								eprintln!("{:?}",e);
+							}
+						},
+						Err(e)=>{
+							//リアクションの一覧に失敗を伝える
+							let res=data_model::ReactionUsers{
+								note_id,
+								reaction,
+								users:Err(format!("get api/notes/reactions error {}",e)),
+								is_next,
+							};
+							if let Err(e)=reaction_ui0.send(res).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					}
+				},
+				LoadSrc::Search(opt) => {
+					let is_next=opt.until_id.is_some();
+					match read_search(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),opt).await{
+						Ok(notes)=>{
+							println!("{} notes found",notes.len());
+							if let Err(e)=raw_note_sender.send(RawNotes::Search(notes,is_next)).await{
+								eprintln!("{:?}",e);
+							}
+						},
+						Err(e)=>{
+							//検索画面に失敗を伝える
+							if let Err(e)=search_ui0.send(Err(format!("get api/notes/search error {}",e))).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					}
+				},
 			}
 		}
 	});
@@ -183,6 +479,288 @@ pub async fn load_misskey(
 					});
 				}
 			},
+			RawNotes::Column(column,notes) => {
+				let mut note_load=vec![];
+				for note in notes{
+					if let Some((n,is_cache)) = load_note(note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache).await {
+						note_cache.insert(n.id.to_owned(),n.clone());
+						if let Err(e)=deck_ui.send((column,n.clone())).await{
+							eprintln!("{:?}",e);
+						}
+						if !is_cache{
+							note_load.push(n);
+							cacche_clean_wait_count-=1;
+						}
+					}
+				}
+				for n in note_load.into_iter().rev(){
+					let delay_assets=delay_assets.clone();
+					tokio::runtime::Handle::current().spawn(async move{
+						if let Err(e)=delay_assets.send(DelayAssets::Note(n)).await{
+							eprintln!("{:?}",e);
+						}
+					});
+				}
+			},
+			RawNotes::Emojis(cache) => {
+				emoji_cache=cache;
+			},
+			RawNotes::Notifications(notifications,is_new) => {
+				for raw in notifications{
+					let n=load_notification(raw,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache).await;
+					let n=Arc::new(n);
+					let delay_assets=delay_assets.clone();
+					let assets=(n.note.clone(),n.user.clone(),n.reaction.as_ref().map(|e|e.url_image().clone()));
+					tokio::runtime::Handle::current().spawn(async move{
+						let (note,user,reaction)=assets;
+						if let Some(note)=note{
+							let _=delay_assets.send(DelayAssets::Note(note)).await;
+						}
+						if let Some(user)=user{
+							let _=delay_assets.send(DelayAssets::User(user)).await;
+						}
+						if let Some(img)=reaction{
+							if !img.loaded(){
+								let _=delay_assets.send(DelayAssets::Image(img)).await;
+							}
+						}
+					});
+					if let Err(e)=notification_ui.send((n,is_new)).await{
+						eprintln!("{:?}",e);
+					}
+					cacche_clean_wait_count-=1;
+				}
+			},
+			RawNotes::Thread(raw) => {
+				let mut note_load=vec![];
+				let mut ancestors=vec![];
+				for note in raw.ancestors{
+					if let Some(n)=load_note_with_assets(note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache,&mut note_load).await{
+						ancestors.push(n);
+					}
+				}
+				let note=load_note_with_assets(raw.note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache,&mut note_load).await;
+				let mut children=vec![];
+				for (depth,note) in raw.children{
+					if let Some(n)=load_note_with_assets(note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache,&mut note_load).await{
+						children.push((depth,n));
+					}
+				}
+				cacche_clean_wait_count-=note_load.len() as i32;
+				let delay_assets=delay_assets.clone();
+				tokio::runtime::Handle::current().spawn(async move{
+					for n in note_load{
+						if let Err(e)=delay_assets.send(DelayAssets::Note(n)).await{
+							eprintln!("{:?}",e);
+						}
+					}
+				});
+				let thread=data_model::NoteThread{
+					id:raw.id,
+					ancestors,
+					note,
+					children,
+				};
+				if let Err(e)=thread_ui.send(Ok(thread)).await{
+					eprintln!("{:?}",e);
+				}
+			},
+			RawNotes::UserDetail(raw) => {
+				let raw=*raw;
+				let user=note_user(&mut user_cache,&mut instance_cache,&raw.user,&emoji_cache).await;
+				let emojis=raw.user.emojis.as_ref();
+				let description=data_model::MFMString::new_opt(raw.description,emojis,user.instance.as_ref(),&emoji_cache).await;
+				let mut fields=vec![];
+				for f in raw.fields{
+					let name=data_model::MFMString::new_simple(f.name,emojis,user.instance.as_ref(),&emoji_cache).await;
+					let value=data_model::MFMString::new(f.value,emojis,user.instance.as_ref(),&emoji_cache).await;
+					fields.push((name,value));
+				}
+				let mut note_load=vec![];
+				let mut pinned_notes=vec![];
+				for note in raw.pinned_notes{
+					if let Some(n)=load_note_with_assets(note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache,&mut note_load).await{
+						pinned_notes.push(n);
+					}
+				}
+				let detail=data_model::UserDetail{
+					user,
+					banner:raw.banner_url.map(|url|Arc::new(url.into())),
+					description,
+					fields,
+					followers_count:raw.followers_count.unwrap_or_default(),
+					following_count:raw.following_count.unwrap_or_default(),
+					notes_count:raw.notes_count.unwrap_or_default(),
+					created_at:chrono::DateTime::parse_from_rfc3339(&raw.created_at).map(|t|t.to_utc()).unwrap_or_else(|_|chrono::Utc::now()),
+					pinned_notes,
+				};
+				let mut images:Vec<Arc<data_model::UrlImage>>=detail.emojis().filter(|img|!img.loaded()).cloned().collect();
+				if let Some(banner)=detail.banner.as_ref(){
+					images.push(banner.clone());
+				}
+				let delay_assets=delay_assets.clone();
+				let user=detail.user.clone();
+				tokio::runtime::Handle::current().spawn(async move{
+					let _=delay_assets.send(DelayAssets::User(user)).await;
+					for img in images{
+						let _=delay_assets.send(DelayAssets::Image(img)).await;
+					}
+					for n in note_load{
+						if let Err(e)=delay_assets.send(DelayAssets::Note(n)).await{
+							eprintln!("{:?}",e);
+						}
+					}
+				});
+				if let Err(e)=user_ui.send(detail).await{
+					eprintln!("{:?}",e);
+				}
+			},
+			RawNotes::Reactions(note_id,reaction,list,is_next) => {
+				let mut users=vec![];
+				for raw in list{
+					let user=note_user(&mut user_cache,&mut instance_cache,&raw.user,&emoji_cache).await;
+					users.push((raw.id,user));
+				}
+				let delay_assets=delay_assets.clone();
+				let load:Vec<_>=users.iter().map(|(_,u)|u.clone()).collect();
+				tokio::runtime::Handle::current().spawn(async move{
+					for user in load{
+						let _=delay_assets.send(DelayAssets::User(user)).await;
+					}
+				});
+				let res=data_model::ReactionUsers{
+					note_id,
+					reaction,
+					users:Ok(users),
+					is_next,
+				};
+				if let Err(e)=reaction_ui.send(res).await{
+					eprintln!("{:?}",e);
+				}
+			},
+			RawNotes::NoteUpdated(ev) => {
+				match ev.t.as_str(){
+					"reacted"|"unreacted"=>{
+						let raw=match serde_json::value::from_value::<RawNoteReaction>(ev.body){
+							Ok(raw)=>raw,
+							Err(e)=>{
+								eprintln!("{:?}",e);
+								continue;
+							}
+						};
+						let mine=raw.user_id.is_some()&&raw.user_id==*my_id.lock().unwrap();
+						let reacted=ev.t.as_str()=="reacted";
+						//保存済みのノートは表示していなくても書き換える
+						store.update_reaction(ev.id.clone(),raw.reaction.clone(),raw.emoji.as_ref().map(|e|(e.name.clone(),e.url.clone())),mine,reacted);
+						let note=match note_cache.get(&ev.id){
+							Some(note)=>note.clone(),
+							None=>continue,
+						};
+						//自分のリアクションはreaction_sendの後に読み直しているので反映済みなら無視する
+						if mine&&note.reactions.is_mine(&raw.reaction)==reacted{
+							continue;
+						}
+						let reactions=if reacted{
+							let emojis=raw.emoji.map(|e|HashMap::from([(e.name,e.url)])).unwrap_or_default();
+							note.reactions.reacted(&raw.reaction,&emojis,&emoji_cache,mine).await
+						}else{
+							note.reactions.unreacted(&raw.reaction,mine)
+						};
+						let note=Arc::new(note.with_reactions(reactions));
+						//単純なリノートも差し替える
+						let mut updated=vec![note.clone()];
+						for n in note_cache.values(){
+							if n.is_simple_renote()&&n.quote.as_ref().map(|q|q.id==note.id).unwrap_or(false){
+								updated.push(Arc::new(n.with_quote(note.clone())));
+							}
+						}
+						let images:Vec<_>=note.reactions.emojis().filter(|img|!img.loaded()).cloned().collect();
+						let delay_assets=delay_assets.clone();
+						tokio::runtime::Handle::current().spawn(async move{
+							for img in images{
+								let _=delay_assets.send(DelayAssets::Image(img)).await;
+							}
+						});
+						for n in updated{
+							note_cache.insert(n.id.clone(),n.clone());
+							if let Err(e)=note_update_ui.send(NoteUpdate::Updated(n)).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					},
+					"deleted"=>{
+						store.delete_note(ev.id.clone());
+						let mut deleted=vec![ev.id.clone()];
+						for (id,n) in note_cache.iter(){
+							if n.is_simple_renote()&&n.quote.as_ref().map(|q|q.id==ev.id).unwrap_or(false){
+								deleted.push(id.clone());
+							}
+						}
+						for id in deleted{
+							note_cache.remove(&id);
+							if let Err(e)=note_update_ui.send(NoteUpdate::Deleted(id)).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					},
+					"pollVoted"=>{
+						let raw=match serde_json::value::from_value::<RawPollVoted>(ev.body){
+							Ok(raw)=>raw,
+							Err(e)=>{
+								eprintln!("{:?}",e);
+								continue;
+							}
+						};
+						let note=match note_cache.get(&ev.id){
+							Some(note)=>note.clone(),
+							None=>continue,
+						};
+						let poll=match note.poll.as_ref(){
+							Some(poll)=>poll,
+							None=>continue,
+						};
+						let mine=raw.user_id.is_some()&&raw.user_id==*my_id.lock().unwrap();
+						let note=Arc::new(note.with_poll(poll.voted(raw.choice,mine)));
+						//単純なリノートも差し替える
+						let mut updated=vec![note.clone()];
+						for n in note_cache.values(){
+							if n.is_simple_renote()&&n.quote.as_ref().map(|q|q.id==note.id).unwrap_or(false){
+								updated.push(Arc::new(n.with_quote(note.clone())));
+							}
+						}
+						for n in updated{
+							note_cache.insert(n.id.clone(),n.clone());
+							if let Err(e)=note_update_ui.send(NoteUpdate::Updated(n)).await{
+								eprintln!("{:?}",e);
+							}
+						}
+					},
+					_=>{
+						println!("unknown note event {} {}",ev.t,ev.id);
+					},
+				}
+			},
+			RawNotes::Search(raw,is_next) => {
+				let mut note_load=vec![];
+				let mut notes=vec![];
+				for note in raw{
+					if let Some(n)=load_note_with_assets(note,&mut note_cache,&mut user_cache,&mut instance_cache,&mut file_cache,&emoji_cache,&mut note_load).await{
+						notes.push(n);
+					}
+				}
+				cacche_clean_wait_count-=note_load.len() as i32;
+				let delay_assets=delay_assets.clone();
+				tokio::runtime::Handle::current().spawn(async move{
+					for n in note_load{
+						if let Err(e)=delay_assets.send(DelayAssets::Note(n)).await{
+							eprintln!("{:?}",e);
+						}
+					}
+				});
+				if let Err(e)=search_ui.send(Ok((notes,is_next))).await{
+					eprintln!("{:?}",e);
+				}
+			},
 		}
 		if cacche_clean_wait_count>0{
 			continue;
@@ -258,62 +836,226 @@ pub async fn load_misskey(
 struct WSState{
 	stream:Option<Arc<WSStream>>,
 	now_stream:Option<u32>,
+	main_stream:Option<u32>,
+	//購読中のノートと参照数
+	captured:HashMap<String,usize>,
+	//再接続時の取りこぼし補完に使う最新のノートID
+	since_id:Option<Arc<std::sync::Mutex<Option<String>>>>,
+	//デッキの列ごとのチャンネルと最新のノートID
+	columns:HashMap<u32,(u32,Arc<std::sync::Mutex<Option<String>>>)>,
+	store:Arc<NoteStore>,
+	client:Client,
+	state_ui:tokio::sync::watch::Sender<StreamState>,
 }
-async fn read_websocket(config:Arc<ConfigFile>,sender:tokio::sync::mpsc::Sender<RawNotes>,v:Option<MisskeyChannel>,state:&mut WSState)->Result<(),reqwest_websocket::Error>{
-	if let Some(ch)=v{
-		if state.stream.is_none(){
-			state.stream=Some({
-				use reqwest_websocket::RequestBuilderExt;
-				let url=reqwest::Url::parse(config.instance.as_ref().unwrap());
-				let mut url=match url {
-					Ok(url)=>url,
-					Err(e)=>{
-						eprintln!("{:?}",e);
-						return Ok(());
+impl WSState{
+	async fn capture(&mut self,capture:NoteCapture)->Result<(),reqwest_websocket::Error>{
+		match capture{
+			NoteCapture::Sub(id)=>{
+				let count=self.captured.entry(id.clone()).or_insert(0);
+				*count+=1;
+				if *count==1{
+					if let Some(stream)=self.stream.as_ref(){
+						stream.sub_note(&id).await?;
 					}
+				}
+			},
+			NoteCapture::Unsub(id)=>{
+				let count=match self.captured.get_mut(&id){
+					Some(count)=>count,
+					None=>return Ok(()),
 				};
-				if url.scheme()=="http"{
-					url.set_scheme("ws").unwrap();
-				}else{
-					url.set_scheme("wss").unwrap();
+				*count-=1;
+				if *count==0{
+					self.captured.remove(&id);
+					if let Some(stream)=self.stream.as_ref(){
+						stream.unsub_note(&id).await?;
+					}
 				}
-				url.set_path("streaming");
-				let query=format!("i={}",config.token.as_ref().unwrap());
-				url.set_query(Some(&query));
-				// create a GET request, upgrade it and send it.
-				let response = Client::default()
-					.get(url)
-					.upgrade() // <-- prepares the websocket upgrade.
-					.send()
-					.await?;
-				let websocket = response.into_websocket().await?;
-				let ws=Arc::new(WSStream::new(websocket));
-				let ws0=ws.clone();
-				tokio::runtime::Handle::current().spawn(async move{
-					let _=ws0.load().await;
+			},
+		}
+		Ok(())
+	}
+}
+fn update_since_id(since_id:&std::sync::Mutex<Option<String>>,id:&str){
+	let mut since_id=since_id.lock().unwrap();
+	if since_id.as_ref().map(|since|since.as_str()<id).unwrap_or(true){
+		since_id.replace(id.to_owned());
+	}
+}
+//タイムラインのノートを送る先を決める
+type RawNotesWrap=Arc<dyn Fn(Vec<RawNote>)->RawNotes+Send+Sync>;
+async fn open_stream(config:&Arc<ConfigFile>,sender:&tokio::sync::mpsc::Sender<RawNotes>,state:&mut WSState)->Result<bool,reqwest_websocket::Error>{
+	//再接続待ちの接続は捨てて繋ぎ直す
+	if let Some(stream)=state.stream.as_ref().filter(|stream|!stream.is_connected()){
+		stream.close_connection().await;
+		state.stream=None;
+		state.main_stream=None;
+		state.now_stream=None;
+		state.columns.clear();
+	}
+	if state.stream.is_none(){
+		let _=state.state_ui.send(StreamState::Connecting);
+		state.stream=Some({
+			let url=reqwest::Url::parse(config.instance.as_ref().unwrap());
+			let mut url=match url {
+				Ok(url)=>url,
+				Err(e)=>{
+					eprintln!("{:?}",e);
+					return Ok(false);
+				}
+			};
+			if url.scheme()=="http"{
+				url.set_scheme("ws").unwrap();
+			}else{
+				url.set_scheme("wss").unwrap();
+			}
+			url.set_path("streaming");
+			let query=format!("i={}",config.token.as_ref().unwrap());
+			url.set_query(Some(&query));
+			let websocket=ws_connect(&url).await?;
+			let note_sender=sender.clone();
+			let ws=Arc::new(WSStream::new(url,websocket,state.state_ui.clone(),move|res: WSChannel|{
+				let sender=note_sender.clone();
+				let f:futures::future::BoxFuture<'static,()>=Box::pin(async move{
+					if let Err(e)=sender.send(RawNotes::NoteUpdated(res)).await{
+						eprintln!("{:?}",e);
+					}
 				});
-				println!("=============Open Connection===============");
-				ws
+				f
+			}));
+			let ws0=ws.clone();
+			tokio::runtime::Handle::current().spawn(async move{
+				let _=ws0.load().await;
 			});
-		}
+			println!("=============Open Connection===============");
+			//接続し直した時は購読し直す
+			for id in state.captured.keys(){
+				ws.sub_note(id).await?;
+			}
+			ws
+		});
+		let _=state.state_ui.send(StreamState::Connected);
+	}
+	if state.main_stream.is_none(){
 		let sender=sender.clone();
-		println!("=============Open Stream===============");
-		state.stream.as_ref().unwrap().open(move|res: WSChannel|{
+		//通知はメインチャンネルから受け取る
+		let id=state.stream.as_ref().unwrap().open(move|res: WSChannel|{
 			let sender=sender.clone();
 			let f:futures::future::BoxFuture<'static,()>=Box::pin(async move{
-				if res.t.as_str()=="note"{
-					if let Ok(note)=serde_json::value::from_value::<RawNote>(res.body){
-						if let Err(e)=sender.send(RawNotes::Single(note)).await{
+				if res.t.as_str()=="notification"{
+					if let Ok(notification)=serde_json::value::from_value::<RawNotification>(res.body){
+						if let Err(e)=sender.send(RawNotes::Notifications(vec![notification],true)).await{
 							eprintln!("{:?}",e);
 						}
 					}
 				}
 			});
 			f
-		},ch).await?;
-		if let Some(id)=state.now_stream{
-			if state.stream.as_ref().unwrap().close_channel(id).await.is_ok(){
-				state.now_stream=Some(id);
+		},MisskeyChannel::Main).await?;
+		state.main_stream=Some(id);
+	}
+	Ok(true)
+}
+//タイムラインのチャンネルを開いて再接続時の補完を登録する
+async fn open_timeline_channel(config:Arc<ConfigFile>,sender:tokio::sync::mpsc::Sender<RawNotes>,(tl,limit):(TimeLine,u8),stream:&WSStream,(store,client):(Arc<NoteStore>,Client),wrap:RawNotesWrap)->Result<(u32,Arc<std::sync::Mutex<Option<String>>>),reqwest_websocket::Error>{
+	let since_id=Arc::new(std::sync::Mutex::new(None));
+	let sender0=sender.clone();
+	let since_id0=since_id.clone();
+	let wrap0=wrap.clone();
+	let store0=store.clone();
+	let tl0=tl.clone();
+	println!("=============Open Stream===============");
+	let id=stream.open(move|res: WSChannel|{
+		let sender=sender0.clone();
+		let since_id=since_id0.clone();
+		let wrap=wrap0.clone();
+		let store=store0.clone();
+		let tl=tl0.clone();
+		let f:futures::future::BoxFuture<'static,()>=Box::pin(async move{
+			if res.t.as_str()=="note"{
+				if let Ok(note)=serde_json::value::from_value::<RawNote>(res.body){
+					update_since_id(&since_id,&note.id);
+					store.save_notes(&tl,std::slice::from_ref(&note));
+					if let Err(e)=sender.send(wrap(vec![note])).await{
+						eprintln!("{:?}",e);
+					}
+				}
+			}
+		});
+		f
+	},tl.clone().into()).await?;
+	//再接続したら切断中のノートをsinceIdで取得する
+	let since_id0=since_id.clone();
+	stream.set_reconnect_listener(id,move||{
+		let sender=sender.clone();
+		let since_id=since_id0.clone();
+		let config=config.clone();
+		let tl=tl.clone();
+		let wrap=wrap.clone();
+		let store=store.clone();
+		let client=client.clone();
+		let f:futures::future::BoxFuture<'static,()>=Box::pin(async move{
+			//1ページに収まらなければ追いつくまで続けて取得する
+			loop{
+				let last=since_id.lock().unwrap().clone();
+				let opt=TLOption{
+					until_id:None,
+					since_id:last.clone(),
+					limit,
+					tl:tl.clone(),
+					known_notes:vec![],
+					websocket:true,
+				};
+				println!("backfill since {:?}",opt.since_id);
+				match read_timeline(&client,config.instance.as_ref().unwrap(),config.token.as_ref().unwrap().clone(),opt).await{
+					Ok(notes)=>{
+						println!("{} notes backfilled",notes.len());
+						let full=notes.len()>=limit as usize;
+						for note in &notes{
+							update_since_id(&since_id,&note.id);
+						}
+						store.save_notes(&tl,&notes);
+						if let Err(e)=sender.send(wrap(notes)).await{
+							eprintln!("{:?}",e);
+							break;
+						}
+						if !full||*since_id.lock().unwrap()==last{
+							break;
+						}
+					},
+					Err(e)=>{
+						eprintln!("backfill error {}",e);
+						break;
+					}
+				}
+			}
+		});
+		f
+	}).await;
+	Ok((id,since_id))
+}
+//どのチャンネルも使っていなければ切断する
+async fn close_unused_stream(state:&mut WSState){
+	if state.now_stream.is_some()||!state.columns.is_empty(){
+		return;
+	}
+	state.main_stream=None;
+	if let Some(stream)=state.stream.take(){
+		stream.close_connection().await;
+	}
+	let _=state.state_ui.send(StreamState::Disconnected);
+}
+async fn read_websocket(config:Arc<ConfigFile>,sender:tokio::sync::mpsc::Sender<RawNotes>,v:Option<(TimeLine,u8)>,state:&mut WSState)->Result<(),reqwest_websocket::Error>{
+	if let Some(v)=v{
+		if !open_stream(&config,&sender,state).await?{
+			return Ok(());
+		}
+		let stream=state.stream.clone().unwrap();
+		let (id,since_id)=open_timeline_channel(config,sender,v,&stream,(state.store.clone(),state.client.clone()),Arc::new(RawNotes::Array)).await?;
+		state.since_id=Some(since_id);
+		if let Some(old)=state.now_stream.replace(id){
+			if let Err(e)=stream.close_channel(old).await{
+				println!("close stream error {:?}",e);
 			}
 		}
 	}else{
@@ -322,15 +1064,105 @@ async fn read_websocket(config:Arc<ConfigFile>,sender:tokio::sync::mpsc::Sender<
 				println!("close stream error {:?}",e);
 			}
 		}
-		if let Some(stream)=state.stream.take(){
-			stream.close_connection().await;
-		}
+		state.since_id=None;
+		close_unused_stream(state).await;
 	}
 	Ok(())
 }
+//デッキの列を同じ接続のチャンネルとして開く
+async fn open_column(config:Arc<ConfigFile>,sender:tokio::sync::mpsc::Sender<RawNotes>,column:u32,v:(TimeLine,u8),state:&mut WSState)->Result<Option<Arc<std::sync::Mutex<Option<String>>>>,reqwest_websocket::Error>{
+	if !open_stream(&config,&sender,state).await?{
+		return Ok(None);
+	}
+	let stream=state.stream.clone().unwrap();
+	let (id,since_id)=open_timeline_channel(config,sender,v,&stream,(state.store.clone(),state.client.clone()),Arc::new(move|notes|RawNotes::Column(column,notes))).await?;
+	if let Some((old,_))=state.columns.insert(column,(id,since_id.clone())){
+		if let Err(e)=stream.close_channel(old).await{
+			println!("close stream error {:?}",e);
+		}
+	}
+	Ok(Some(since_id))
+}
+async fn close_column(column:u32,state:&mut WSState){
+	if let Some((id,_))=state.columns.remove(&column){
+		if let Some(stream)=state.stream.as_ref(){
+			if let Err(e)=stream.close_channel(id).await{
+				println!("close stream error {:?}",e);
+			}
+		}
+	}
+	close_unused_stream(state).await;
+}
 enum RawNotes{
 	Single(RawNote),
 	Array(Vec<RawNote>),
+	//デッキの列ID
+	Column(u32,Vec<RawNote>),
+	//取り直したカスタム絵文字
+	Emojis(EmojiCache),
+	Notifications(Vec<RawNotification>,bool),
+	Thread(RawThread),
+	Search(Vec<RawNote>,bool),
+	UserDetail(Box<RawUserDetail>),
+	Reactions(String,String,Vec<RawReaction>,bool),
+	NoteUpdated(WSChannel),
+}
+#[derive(Deserialize,Debug)]
+struct RawNoteReaction{
+	reaction:String,
+	emoji:Option<RawReactionEmoji>,
+	#[serde(rename = "userId")]
+	user_id:Option<String>,
+}
+#[derive(Deserialize,Debug)]
+struct RawPollVoted{
+	choice:usize,
+	#[serde(rename = "userId")]
+	user_id:Option<String>,
+}
+#[derive(Deserialize,Debug)]
+struct RawReactionEmoji{
+	name:String,
+	url:String,
+}
+#[derive(Deserialize,Debug)]
+struct RawReaction{
+	id:String,
+	user:RawUser,
+}
+#[derive(Deserialize,Debug)]
+struct RawUserDetail{
+	#[serde(flatten)]
+	user:RawUser,
+	#[serde(rename = "bannerUrl")]
+	banner_url:Option<String>,
+	description:Option<String>,
+	#[serde(default)]
+	fields:Vec<RawUserField>,
+	#[serde(rename = "followersCount")]
+	followers_count:Option<u64>,
+	#[serde(rename = "followingCount")]
+	following_count:Option<u64>,
+	#[serde(rename = "notesCount")]
+	notes_count:Option<u64>,
+	#[serde(rename = "createdAt")]
+	created_at:String,
+	#[serde(rename = "pinnedNotes")]
+	#[serde(default)]
+	pinned_notes:Vec<RawNote>,
+}
+#[derive(Deserialize,Debug)]
+struct RawUserField{
+	name:String,
+	value:String,
+}
+struct RawThread{
+	id:String,
+	note:RawNote,
+	//根から順
+	ancestors:Vec<RawNote>,
+	//深さと返信
+	children:Vec<(usize,RawNote)>,
 }
 struct WSChannelListener(Box<dyn FnMut(WSChannel)->futures::future::BoxFuture<'static, ()>+Send+Sync>);
 impl <F> From<F> for WSChannelListener where F:FnMut(WSChannel)->futures::future::BoxFuture<'static, ()>+Send+Sync+'static{
@@ -338,15 +1170,53 @@ impl <F> From<F> for WSChannelListener where F:FnMut(WSChannel)->futures::future
 		Self(Box::new(value))
 	}
 }
+struct WSReconnectListener(Box<dyn FnMut()->futures::future::BoxFuture<'static, ()>+Send+Sync>);
+impl <F> From<F> for WSReconnectListener where F:FnMut()->futures::future::BoxFuture<'static, ()>+Send+Sync+'static{
+	fn from(value: F) -> Self {
+		Self(Box::new(value))
+	}
+}
 pub enum MisskeyChannel{
+	Main,
 	GlobalTimeline,
 	HomeTimeline,
+	LocalTimeline,
+	HybridTimeline,
+	UserList(String),
+	Antenna(String),
+	Hashtag(String),
 }
 impl MisskeyChannel{
 	pub fn id(&self)->&'static str{
 		match self {
+			MisskeyChannel::Main => "main",
 			MisskeyChannel::GlobalTimeline => "globalTimeline",
 			MisskeyChannel::HomeTimeline => "homeTimeline",
+			MisskeyChannel::LocalTimeline => "localTimeline",
+			MisskeyChannel::HybridTimeline => "hybridTimeline",
+			MisskeyChannel::UserList(_) => "userList",
+			MisskeyChannel::Antenna(_) => "antenna",
+			MisskeyChannel::Hashtag(_) => "hashtag",
+		}
+	}
+	pub fn params(&self)->serde_json::Value{
+		match self {
+			MisskeyChannel::UserList(id) => serde_json::json!({
+				"listId":id,
+				"withRenotes":true,
+				"withFiles":false,
+			}),
+			MisskeyChannel::Antenna(id) => serde_json::json!({
+				"antennaId":id,
+			}),
+			MisskeyChannel::Hashtag(tag) => serde_json::json!({
+				"q":[[tag]],
+			}),
+			MisskeyChannel::Main => serde_json::json!({}),
+			_ => serde_json::json!({
+				"withRenotes":true,
+				"withCats":false,
+			}),
 		}
 	}
 }
@@ -355,35 +1225,81 @@ impl From<TimeLine> for MisskeyChannel{
 		match value {
 			TimeLine::Global => Self::GlobalTimeline,
 			TimeLine::Home => Self::HomeTimeline,
+			TimeLine::Local => Self::LocalTimeline,
+			TimeLine::Social => Self::HybridTimeline,
+			TimeLine::List(id) => Self::UserList(id),
+			TimeLine::Antenna(id) => Self::Antenna(id),
+			TimeLine::Hashtag(tag) => Self::Hashtag(tag),
 			TimeLine::User(_)=>unimplemented!(),
 		}
 	}
 }
 struct WSStream{
+	url:reqwest::Url,
 	channel_listener:Arc<Mutex<HashMap<u32,WSChannelListener>>>,
+	note_listener:Arc<Mutex<WSChannelListener>>,
+	reconnect_listener:Arc<Mutex<HashMap<u32,WSReconnectListener>>>,
+	//再接続時に送り直すconnectメッセージ
+	connect_messages:Arc<Mutex<HashMap<u32,String>>>,
+	notes:Arc<Mutex<HashSet<String>>>,
 	last_id:AtomicU32,
 	send: Arc<Mutex<futures::prelude::stream::SplitSink<reqwest_websocket::WebSocket, reqwest_websocket::Message>>>,
 	recv: Mutex<Option<futures::prelude::stream::SplitStream<reqwest_websocket::WebSocket>>>,
 	exit: Arc<AtomicBool>,
+	connected: Arc<AtomicBool>,
+	state_ui: tokio::sync::watch::Sender<StreamState>,
+}
+async fn ws_connect(url:&reqwest::Url)->Result<reqwest_websocket::WebSocket,reqwest_websocket::Error>{
+	use reqwest_websocket::RequestBuilderExt;
+	// create a GET request, upgrade it and send it.
+	let response = Client::default()
+		.get(url.clone())
+		.upgrade() // <-- prepares the websocket upgrade.
+		.send()
+		.await?;
+	response.into_websocket().await
 }
+const RECONNECT_MIN_WAIT:u64=1000;
+const RECONNECT_MAX_WAIT:u64=60*1000;
 impl WSStream{
-	fn new(websocket:reqwest_websocket::WebSocket)->Self{
+	fn new(url:reqwest::Url,websocket:reqwest_websocket::WebSocket,state_ui:tokio::sync::watch::Sender<StreamState>,note_listener:impl Into<WSChannelListener>)->Self{
 		let (send,recv)=websocket.split();
 		Self{
+			url,
 			channel_listener:Arc::new(Mutex::new(HashMap::new())),
+			note_listener:Arc::new(Mutex::new(note_listener.into())),
+			reconnect_listener:Arc::new(Mutex::new(HashMap::new())),
+			connect_messages:Arc::new(Mutex::new(HashMap::new())),
+			notes:Arc::new(Mutex::new(HashSet::new())),
 			last_id:AtomicU32::new(0),
 			send:Arc::new(Mutex::new(send)),
 			recv:Mutex::new(Some(recv)),
 			exit:Arc::new(AtomicBool::new(false)),
+			connected:Arc::new(AtomicBool::new(true)),
+			state_ui,
 		}
 	}
+	fn is_connected(&self)->bool{
+		self.connected.load(std::sync::atomic::Ordering::Relaxed)
+	}
+	async fn set_reconnect_listener(&self,id:u32,listener:impl Into<WSReconnectListener>){
+		self.reconnect_listener.lock().await.insert(id,listener.into());
+	}
 	async fn open(&self,listener:impl Into<WSChannelListener>,channel:MisskeyChannel)->Result<u32,reqwest_websocket::Error>{
 		let mut websocket=self.send.lock().await;
 		let id=self.last_id.fetch_add(1,std::sync::atomic::Ordering::SeqCst);
 		println!("open channel... {}",id);
 		let mut channel_listener=self.channel_listener.lock().await;
 		channel_listener.insert(id,listener.into());
-		let q=format!("{{\"type\":\"connect\",\"body\":{{\"channel\":\"{}\",\"id\":\"{}\",\"params\":{{\"withRenotes\":true,\"withCats\":false}}}}}}",channel.id(),id);
+		let q=serde_json::json!({
+			"type":"connect",
+			"body":{
+				"channel":channel.id(),
+				"id":id.to_string(),
+				"params":channel.params(),
+			},
+		}).to_string();
+		self.connect_messages.lock().await.insert(id,q.clone());
 		websocket.send(reqwest_websocket::Message::Text(q.into())).await?;
 		println!("opend channel {}",id);
 		Ok(id)
@@ -391,61 +1307,154 @@ impl WSStream{
 	async fn close_channel(&self,id:u32)->Result<u32,reqwest_websocket::Error>{
 		println!("close channel... {}",id);
 		let mut websocket=self.send.lock().await;
+		self.connect_messages.lock().await.remove(&id);
 		let q=format!("{{\"type\":\"disconnect\",\"body\":{{\"id\":\"{}\"}}}}",id);
 		websocket.send(reqwest_websocket::Message::Text(q.into())).await?;
 		let mut channel_listener=self.channel_listener.lock().await;
 		channel_listener.remove(&id);
+		self.reconnect_listener.lock().await.remove(&id);
 		println!("closed channel {}",id);
 		Ok(id)
 	}
+	async fn sub_note(&self,id:&str)->Result<(),reqwest_websocket::Error>{
+		let mut websocket=self.send.lock().await;
+		self.notes.lock().await.insert(id.to_owned());
+		websocket.send(reqwest_websocket::Message::Text(sub_note_message(id).into())).await
+	}
+	async fn unsub_note(&self,id:&str)->Result<(),reqwest_websocket::Error>{
+		let mut websocket=self.send.lock().await;
+		self.notes.lock().await.remove(id);
+		let q=serde_json::json!({
+			"type":"unsubNote",
+			"body":{
+				"id":id,
+			},
+		}).to_string();
+		websocket.send(reqwest_websocket::Message::Text(q.into())).await
+	}
 	async fn load(&self){
 		let websocket=self.recv.lock().await.take();
 		if websocket.is_none(){
 			return;
 		}
 		let mut websocket=websocket.unwrap();
+		let url=self.url.clone();
 		let channel_listener=self.channel_listener.clone();
+		let note_listener=self.note_listener.clone();
+		let reconnect_listener=self.reconnect_listener.clone();
+		let connect_messages=self.connect_messages.clone();
+		let notes=self.notes.clone();
 		let sender=self.send.clone();
+		let sender0=self.send.clone();
+		let exit=self.exit.clone();
 		let exit0=self.exit.clone();
+		let connected=self.connected.clone();
+		let connected0=self.connected.clone();
+		let state_ui=self.state_ui.clone();
 		std::thread::spawn(move||{
 			let rt=tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
 			let handle=rt.spawn(async move{
-				while let Ok(Some(message)) = websocket.try_next().await {
-					match message {
-						reqwest_websocket::Message::Text(text) =>{
-							if let Ok(Some(channel))=serde_json::from_str::<WSResult>(text.as_str()).map(|res|{
-								if res.t.as_str()=="channel"{
-									serde_json::value::from_value::<WSChannel>(res.body).ok()
-								}else{
-									None
-								}
-							}){
-								if let Ok(id)=u32::from_str_radix(channel.id.as_str(),10){
-									let mut r=channel_listener.lock().await;
-									if let Some(handle)=r.get_mut(&id){
-										handle.0(channel).await;
-									}else{
-										println!("unknown channel event {}",id);
+				loop{
+					while let Ok(Some(message)) = websocket.try_next().await {
+						if exit.load(std::sync::atomic::Ordering::Relaxed){
+							break;
+						}
+						match message {
+							reqwest_websocket::Message::Text(text) =>{
+								let res=serde_json::from_str::<WSResult>(text.as_str()).ok().and_then(|res|{
+									let t=res.t;
+									serde_json::value::from_value::<WSChannel>(res.body).ok().map(|body|(t,body))
+								});
+								match res{
+									Some((t,channel)) if t.as_str()=="channel"=>{
+										if let Ok(id)=u32::from_str_radix(channel.id.as_str(),10){
+											let mut r=channel_listener.lock().await;
+											if let Some(handle)=r.get_mut(&id){
+												handle.0(channel).await;
+											}else{
+												println!("unknown channel event {}",id);
+											}
+										}
+									},
+									//subNoteで購読したノートの更新
+									Some((t,update)) if t.as_str()=="noteUpdated"=>{
+										let mut r=note_listener.lock().await;
+										r.0(update).await;
+									},
+									_=>{
+										println!("parse error {}",text);
 									}
 								}
-							}else{
-								println!("parse error {}",text);
+							},
+							_=>{}
+						}
+					}
+					connected.store(false,std::sync::atomic::Ordering::Relaxed);
+					println!("close websocket");
+					if exit.load(std::sync::atomic::Ordering::Relaxed){
+						break;
+					}
+					let _=state_ui.send(StreamState::Reconnecting);
+					//切断されたら間隔を倍にしながら再接続する
+					let mut wait=RECONNECT_MIN_WAIT;
+					let new_websocket=loop{
+						println!("reconnect after {}ms",wait);
+						tokio::time::sleep(tokio::time::Duration::from_millis(wait)).await;
+						if exit.load(std::sync::atomic::Ordering::Relaxed){
+							return;
+						}
+						match ws_connect(&url).await{
+							Ok(ws)=>{
+								//待っている間に閉じられていたら捨てる
+								if exit.load(std::sync::atomic::Ordering::Relaxed){
+									return;
+								}
+								break ws;
+							},
+							Err(e)=>{
+								println!("reconnect error {:?}",e);
+								//次の待ち時間の間は失敗の理由を出しておく
+								let _=state_ui.send(StreamState::Failed(e.to_string()));
+								wait=(wait*2).min(RECONNECT_MAX_WAIT);
 							}
-						},
-						_=>{}
+						}
+					};
+					let (send,recv)=new_websocket.split();
+					websocket=recv;
+					{
+						let mut websocket=sender.lock().await;
+						*websocket=send;
+						for q in connect_messages.lock().await.values(){
+							if let Err(e)=websocket.send(reqwest_websocket::Message::Text(q.clone().into())).await{
+								println!("reconnect channel error {:?}",e);
+							}
+						}
+						for id in notes.lock().await.iter(){
+							if let Err(e)=websocket.send(reqwest_websocket::Message::Text(sub_note_message(id).into())).await{
+								println!("reconnect subNote error {:?}",e);
+							}
+						}
+					}
+					connected.store(true,std::sync::atomic::Ordering::Relaxed);
+					let _=state_ui.send(StreamState::Connected);
+					println!("=============Reconnected===============");
+					for listener in reconnect_listener.lock().await.values_mut(){
+						listener.0().await;
 					}
 				}
-				println!("close websocket");
 			});
 			rt.block_on(async{
 				while !exit0.load(std::sync::atomic::Ordering::Relaxed){
-					let mut websocket=sender.lock().await;
-					if let Err(e)=websocket.send(reqwest_websocket::Message::Text("h".into())).await{
-						println!("ping error {:?}",e);
-					}else{
-						println!("ping ok");
+					//切断中は送らない
+					if connected0.load(std::sync::atomic::Ordering::Relaxed){
+						let mut websocket=sender0.lock().await;
+						if let Err(e)=websocket.send(reqwest_websocket::Message::Text("h".into())).await{
+							println!("ping error {:?}",e);
+						}else{
+							println!("ping ok");
+						}
+						drop(websocket);
 					}
-					drop(websocket);
 					tokio::time::sleep(tokio::time::Duration::from_millis(60*1000)).await;
 				}
 			});
@@ -460,6 +1469,20 @@ impl WSStream{
 		println!("closed connection {:?}",res);
 	}
 }
+impl Drop for WSStream{
+	fn drop(&mut self){
+		//ローダーごと破棄された時に再接続を止める
+		self.exit.store(true,std::sync::atomic::Ordering::Relaxed);
+	}
+}
+fn sub_note_message(id:&str)->String{
+	serde_json::json!({
+		"type":"subNote",
+		"body":{
+			"id":id,
+		},
+	}).to_string()
+}
 #[derive(Serialize,Deserialize,Debug)]
 struct WSResult{
 	#[serde(rename = "type")]
@@ -473,6 +1496,76 @@ struct WSChannel{
 	id:String,
 	body:serde_json::Value,
 }
+async fn note_user(
+	user_cache:&mut HashMap<String,Arc<data_model::UserProfile>>,
+	instance_cache: &mut HashMap<String, Arc<data_model::FediverseInstance>>,
+	user:&RawUser,
+	emoji_cache:&data_model::EmojiCache,
+)->Arc<data_model::UserProfile>{
+	let user_id=&user.id;
+	match user_cache.get(user_id){
+		Some(hit)=>hit.clone(),
+		None=>{
+			let user=data_model::UserProfile::load(&user,instance_cache,emoji_cache).await;
+			let user=Arc::new(user);
+			user_cache.insert(user_id.to_owned(),user.clone());
+			user
+		}
+	}
+}
+async fn load_notification(
+	mut notification:RawNotification,
+	note_cache:&mut HashMap<String, Arc<data_model::Note>>,
+	user_cache:&mut HashMap<String, Arc<data_model::UserProfile>>,
+	instance_cache:&mut HashMap<String, Arc<data_model::FediverseInstance>>,
+	file_cache:&mut HashMap<String, NoteFile>,
+	emoji_cache:&EmojiCache,
+)->data_model::Notification{
+	let user=match notification.user.as_ref(){
+		Some(user)=>Some(note_user(user_cache,instance_cache,user,emoji_cache).await),
+		None=>None,
+	};
+	let reaction=match (notification.reaction.as_ref(),notification.note.as_ref()){
+		(Some(reaction),Some(note))=>data_model::Emoji::from_reaction(reaction,&note.reaction_emojis,emoji_cache).await,
+		_=>None,
+	};
+	let note=match notification.note.take(){
+		Some(note)=>{
+			match load_note(note,note_cache,user_cache,instance_cache,file_cache,emoji_cache).await{
+				Some((n,_))=>{
+					note_cache.insert(n.id.to_owned(),n.clone());
+					Some(n)
+				},
+				None=>None,
+			}
+		},
+		None=>None,
+	};
+	data_model::Notification{
+		id:notification.id,
+		kind:notification.t.as_str().into(),
+		user,
+		note,
+		reaction,
+		created_at:chrono::DateTime::parse_from_rfc3339(&notification.created_at).map(|t|t.to_utc()).unwrap_or_else(|_|chrono::Utc::now()),
+	}
+}
+async fn load_note_with_assets(
+	note:RawNote,
+	note_cache:&mut HashMap<String, Arc<data_model::Note>>,
+	user_cache:&mut HashMap<String, Arc<data_model::UserProfile>>,
+	instance_cache:&mut HashMap<String, Arc<data_model::FediverseInstance>>,
+	file_cache:&mut HashMap<String, NoteFile>,
+	emoji_cache:&EmojiCache,
+	note_load:&mut Vec<Arc<data_model::Note>>,
+)->Option<Arc<data_model::Note>>{
+	let (n,is_cache)=load_note(note,note_cache,user_cache,instance_cache,file_cache,emoji_cache).await?;
+	note_cache.insert(n.id.to_owned(),n.clone());
+	if !is_cache{
+		note_load.push(n.clone());
+	}
+	Some(n)
+}
 async fn load_note(
 	mut note:RawNote,
 	note_cache:&mut HashMap<String, Arc<data_model::Note>>,
@@ -488,75 +1581,32 @@ async fn load_note(
 		}
 	}
 	println!("load note {}",note.id);
-	// Print the text of the note, if any.
-	async fn note_user(
-		user_cache:&mut HashMap<String,Arc<data_model::UserProfile>>,
-		instance_cache: &mut HashMap<String, Arc<data_model::FediverseInstance>>,
-		user:&RawUser,
-		emoji_cache:&data_model::EmojiCache,
-	)->Arc<data_model::UserProfile>{
-		let user_id=&user.id;
-		match user_cache.get(user_id){
-			Some(hit)=>hit.clone(),
-			None=>{
-				let user=data_model::UserProfile::load(&user,instance_cache,emoji_cache).await;
-				let user=Arc::new(user);
-				user_cache.insert(user_id.to_owned(),user.clone());
-				user
-			}
-		}
-	}
-	fn note_files(note:&RawNote,file_cache:&mut HashMap<String,data_model::NoteFile>)->Vec<NoteFile>{
-		let mut files=vec![];
-		for f in note.files.iter(){
-			if let Some(hit)=file_cache.get(&f.id){
-				files.push(hit.clone());
-			}else{
-				let id=f.id.to_owned();
-				let f=NoteFile::from(f);
-				file_cache.insert(id,f.clone());
-				files.push(f);
-			}
-		}
-		files
-	}
+	//返信先のノート
+	let reply=match note.reply.take(){
+		Some(reply)=>Some(load_child_note(*reply,note_cache,user_cache,instance_cache,file_cache,emoji_cache).await),
+		None=>None,
+	};
 	if let Some(quote)=note.renote.take(){
 		//引用されたノート
-		let n=if let Some(n)=note_cache.get(&quote.id){
-			n.clone()
-		}else{
-			let user=note_user(user_cache,instance_cache,&quote.user,&emoji_cache).await;
-			let reactions=data_model::Reactions::load(&quote,&emoji_cache).await;
-			let created_at=quote.created_at();
-			let n=Arc::new(crate::data_model::Note{
-				quote:None,
-				created_at,
-				visibility:quote.visibility.as_str().into(),
-				reactions,
-				files:note_files(&quote,file_cache),
-				text:data_model::MFMString::new(quote.text.unwrap_or_default(),quote.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
-				id:quote.id,
-				cw:data_model::MFMString::new_opt(quote.cw,quote.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
-				user,
-				height:AtomicU32::new(0),
-			});
-			note_cache.insert(n.id.to_owned(),n.clone());
-			n
-		};
+		let n=load_child_note(*quote,note_cache,user_cache,instance_cache,file_cache,emoji_cache).await;
 		let user=note_user(user_cache,instance_cache,&note.user,&emoji_cache).await;
 		let reactions=data_model::Reactions::load(&note,&emoji_cache).await;
 		let created_at=note.created_at();
 		Some((Arc::new(crate::data_model::Note{
 			quote:Some(n),
+			reply,
+			reply_id:note.reply_id.clone(),
 			created_at,
 			visibility:note.visibility.as_str().into(),
 			reactions,
 			files:note_files(&note,file_cache),
+			poll:note.poll.take().map(Into::into),
 			text:data_model::MFMString::new(note.text.unwrap_or_default(),note.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
 			id:note.id,
 			cw:data_model::MFMString::new_opt(note.cw,note.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
 			user,
 			height:AtomicU32::new(0),
+			evict_gen:AtomicU64::new(0),
 		}),false))
 	}else if note.text.is_some() {
 		let user=note_user(user_cache,instance_cache,&note.user,&emoji_cache).await;
@@ -564,20 +1614,75 @@ async fn load_note(
 		let created_at=note.created_at();
 		Some((Arc::new(crate::data_model::Note{
 			quote:None,
+			reply,
+			reply_id:note.reply_id.clone(),
 			created_at,
 			visibility:note.visibility.as_str().into(),
 			reactions,
 			files:note_files(&note,file_cache),
+			poll:note.poll.take().map(Into::into),
 			text:data_model::MFMString::new(note.text.unwrap(),note.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
 			id:note.id,
 			cw:data_model::MFMString::new_opt(note.cw,note.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
 			user,
 			height:AtomicU32::new(0),
+			evict_gen:AtomicU64::new(0),
 		}),false))
 	}else{
 		None
 	}
 }
+fn note_files(note:&RawNote,file_cache:&mut HashMap<String,data_model::NoteFile>)->Vec<NoteFile>{
+	let mut files=vec![];
+	for f in note.files.iter(){
+		if let Some(hit)=file_cache.get(&f.id){
+			files.push(hit.clone());
+		}else{
+			let id=f.id.to_owned();
+			let f=NoteFile::from(f);
+			file_cache.insert(id,f.clone());
+			files.push(f);
+		}
+	}
+	files
+}
+//引用や返信先として埋め込まれたノート
+async fn load_child_note(
+	mut child:RawNote,
+	note_cache:&mut HashMap<String, Arc<data_model::Note>>,
+	user_cache:&mut HashMap<String, Arc<data_model::UserProfile>>,
+	instance_cache:&mut HashMap<String, Arc<data_model::FediverseInstance>>,
+	file_cache:&mut HashMap<String, NoteFile>,
+	emoji_cache:&EmojiCache,
+)->Arc<data_model::Note>{
+	if let Some(n)=note_cache.get(&child.id){
+		//リアクションが変わっていなければキャッシュを使う
+		if n.reactions.hash==reactions_hash(&child){
+			return n.clone();
+		}
+	}
+	let user=note_user(user_cache,instance_cache,&child.user,&emoji_cache).await;
+	let reactions=data_model::Reactions::load(&child,&emoji_cache).await;
+	let created_at=child.created_at();
+	let n=Arc::new(crate::data_model::Note{
+		quote:None,
+		reply:None,
+		reply_id:child.reply_id.clone(),
+		created_at,
+		visibility:child.visibility.as_str().into(),
+		reactions,
+		files:note_files(&child,file_cache),
+		poll:child.poll.take().map(Into::into),
+		text:data_model::MFMString::new(child.text.unwrap_or_default(),child.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
+		id:child.id,
+		cw:data_model::MFMString::new_opt(child.cw,child.emojis.as_ref(),user.instance.as_ref(),&emoji_cache).await,
+		user,
+		height:AtomicU32::new(0),
+		evict_gen:AtomicU64::new(0),
+	});
+	note_cache.insert(n.id.to_owned(),n.clone());
+	n
+}
 #[derive(Serialize,Deserialize,Debug)]
 struct RawEmojis{
 	emojis:Vec<RawEmojiElement>
@@ -594,8 +1699,13 @@ async fn emojis(client:&Client,local_instance:&str)->Result<RawEmojis,String>{
 	let meta=meta.bytes().await.map_err(|e|e.to_string())?;
 	serde_json::from_slice(&meta).map_err(|e|e.to_string())
 }
+//名前からURLを引く表にする
+async fn local_emojis(client:&Client,local_instance:&str)->Result<HashMap<String,String>,String>{
+	let emojis=emojis(client,local_instance).await?;
+	Ok(emojis.emojis.into_iter().map(|emoji|(emoji.name,emoji.url)).collect())
+}
 #[derive(Serialize,Deserialize,Debug)]
-struct ApiMeta{
+pub struct ApiMeta{
 	ads:Vec<serde_json::Value>,
 	#[serde(rename = "backgroundImageUrl")]
 	background_image_url:Option<String>,
@@ -620,7 +1730,7 @@ struct ApiMeta{
 	enable_recaptcha:Option<bool>,
 	#[serde(rename = "enableTurnstile")]
 	enable_turnstile:Option<bool>,
-	features:MetaFeatures,
+	pub(crate) features:MetaFeatures,
 	#[serde(rename = "feedbackUrl")]
 	feedback_url:Option<String>,
 	#[serde(rename = "impressumUrl")]
@@ -632,7 +1742,7 @@ struct ApiMeta{
 	#[serde(rename = "maintainerName")]
 	maintainer_name:Option<String>,
 	#[serde(rename = "maxNoteTextLength")]
-	max_note_text_length:Option<u64>,
+	pub(crate) max_note_text_length:Option<u64>,
 	#[serde(rename = "mcaptchaInstanceUrl")]
 	mcaptcha_instance_url:Option<String>,
 	#[serde(rename = "mediaProxy")]
@@ -643,15 +1753,24 @@ struct ApiMeta{
 	not_found_image_url:Option<String>,
 }
 #[derive(Serialize,Deserialize,Debug)]
-struct MetaFeatures{
+pub struct MetaFeatures{
 	#[serde(rename = "globalTimeline")]
-	global_timeline:Option<bool>,
+	pub(crate) global_timeline:Option<bool>,
 	#[serde(rename = "localTimeline")]
-	local_timeline:Option<bool>,
-	miauth:Option<bool>,
+	pub(crate) local_timeline:Option<bool>,
+	pub(crate) miauth:Option<bool>,
 	#[serde(rename = "objectStorage")]
 	object_storage:Option<bool>,
 }
+async fn i(client:&Client,local_instance:&str,token:String)->Result<RawUser,String>{
+	let req_builder=client.post(format!("{}/api/i",local_instance));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
+	let req_builder=req_builder.body(serde_json::json!({"i":token}).to_string());
+	let user=req_builder.send().await.map_err(|e|e.to_string())?;
+	let user=user.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&user).map_err(|e|e.to_string())
+}
 async fn meta(client:&Client,local_instance:&str)->Result<ApiMeta,String>{
 	let req_builder=client.post(format!("{}/api/meta",local_instance));
 	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
@@ -665,7 +1784,12 @@ async fn meta(client:&Client,local_instance:&str)->Result<ApiMeta,String>{
 pub enum TimeLine{
 	Global,
 	Home,
+	Local,
+	Social,
 	User(String),
+	List(String),
+	Antenna(String),
+	Hashtag(String),
 }
 impl Default for TimeLine{
 	fn default() -> Self {
@@ -677,13 +1801,18 @@ impl ToString for TimeLine{
 		match self {
 			TimeLine::Global => "global-timeline",
 			TimeLine::Home => "timeline",
+			TimeLine::Local => "local-timeline",
+			TimeLine::Social => "hybrid-timeline",
 			TimeLine::User(_) => "user",
+			TimeLine::List(_) => "user-list-timeline",
+			TimeLine::Antenna(_) => "antenna",
+			TimeLine::Hashtag(_) => "search-by-tag",
 		}.to_owned()
 	}
 }
 async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOption)->Result<Vec<RawNote>,String>{
 	let req_builder=match opt.tl {
-		TimeLine::Global|TimeLine::Home => {
+		TimeLine::Global|TimeLine::Home|TimeLine::Local|TimeLine::Social => {
 			let req_builder=client.post(format!("{}/api/notes/{}",local_instance,opt.tl.to_string()));
 			let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
 			#[derive(Serialize,Deserialize,Debug)]
@@ -691,6 +1820,9 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 				#[serde(skip_serializing_if = "Option::is_none")]
 				#[serde(rename = "untilId")]
 				until_id:Option<String>,
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "sinceId")]
+				since_id:Option<String>,
 				#[serde(rename = "allowPartial")]
 				allow_partial:bool,
 				#[serde(rename = "withRenotes")]
@@ -700,6 +1832,7 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 			}
 			let req_body=TimelineRequestJson{
 				until_id:opt.until_id,
+				since_id:opt.since_id.clone(),
 				allow_partial: false,
 				with_renotes: true,
 				limit:opt.limit,
@@ -718,6 +1851,9 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 				#[serde(skip_serializing_if = "Option::is_none")]
 				#[serde(rename = "untilId")]
 				until_id:Option<String>,
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "sinceId")]
+				since_id:Option<String>,
 				#[serde(rename = "allowPartial")]
 				allow_partial:bool,
 				#[serde(rename = "withRenotes")]
@@ -735,6 +1871,7 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 			}
 			let req_body=TimelineRequestJson{
 				until_id:opt.until_id,
+				since_id:opt.since_id.clone(),
 				allow_partial: false,
 				with_renotes: true,
 				limit:opt.limit,
@@ -749,6 +1886,98 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 			let req_builder=req_builder.body(req_body);
 			req_builder
 		},
+		TimeLine::List(id) => {
+			let req_builder=client.post(format!("{}/api/notes/user-list-timeline",local_instance));
+			let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+			#[derive(Serialize,Deserialize,Debug)]
+			struct TimelineRequestJson{
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "untilId")]
+				until_id:Option<String>,
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "sinceId")]
+				since_id:Option<String>,
+				#[serde(rename = "allowPartial")]
+				allow_partial:bool,
+				#[serde(rename = "withRenotes")]
+				with_renotes:bool,
+				#[serde(rename = "withFiles")]
+				with_files:bool,
+				#[serde(rename = "listId")]
+				list_id:String,
+				limit:u8,
+				i:String,
+			}
+			let req_body=TimelineRequestJson{
+				until_id:opt.until_id,
+				since_id:opt.since_id.clone(),
+				allow_partial: false,
+				with_renotes: true,
+				with_files: false,
+				list_id: id,
+				limit:opt.limit,
+				i:token,
+			};
+			let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+			let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+			let req_builder=req_builder.body(req_body);
+			req_builder
+		},
+		TimeLine::Antenna(id) => {
+			let req_builder=client.post(format!("{}/api/antennas/notes",local_instance));
+			let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+			#[derive(Serialize,Deserialize,Debug)]
+			struct TimelineRequestJson{
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "untilId")]
+				until_id:Option<String>,
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "sinceId")]
+				since_id:Option<String>,
+				#[serde(rename = "antennaId")]
+				antenna_id:String,
+				limit:u8,
+				i:String,
+			}
+			let req_body=TimelineRequestJson{
+				until_id:opt.until_id,
+				since_id:opt.since_id.clone(),
+				antenna_id: id,
+				limit:opt.limit,
+				i:token,
+			};
+			let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+			let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+			let req_builder=req_builder.body(req_body);
+			req_builder
+		},
+		TimeLine::Hashtag(tag) => {
+			let req_builder=client.post(format!("{}/api/notes/search-by-tag",local_instance));
+			let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+			#[derive(Serialize,Deserialize,Debug)]
+			struct TimelineRequestJson{
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "untilId")]
+				until_id:Option<String>,
+				#[serde(skip_serializing_if = "Option::is_none")]
+				#[serde(rename = "sinceId")]
+				since_id:Option<String>,
+				tag:String,
+				limit:u8,
+				i:String,
+			}
+			let req_body=TimelineRequestJson{
+				until_id:opt.until_id,
+				since_id:opt.since_id.clone(),
+				tag,
+				limit:opt.limit,
+				i:token,
+			};
+			let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+			let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+			let req_builder=req_builder.body(req_body);
+			req_builder
+		},
 	};
 	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
 	let htl=req_builder.send().await;
@@ -759,7 +1988,11 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 	let htl=htl.bytes().await;
 	let htl=htl.map_err(|e|e.to_string())?;
 	let htl=serde_json::from_slice(&htl);
-	let htl: Vec<RawNote>=htl.map_err(|e|e.to_string())?;
+	let mut htl: Vec<RawNote>=htl.map_err(|e|e.to_string())?;
+	if opt.since_id.is_some(){
+		//sinceIdだけの指定では古い順に返るので新しい順に揃える
+		htl.sort_by(|a,b|b.id.cmp(&a.id));
+	}
 	let mut known_notes_map=HashMap::new();
 	for note in &opt.known_notes{
 		known_notes_map.insert(&note.id,note);
@@ -777,6 +2010,187 @@ async fn read_timeline(client:&Client,local_instance:&str,token:String,opt:TLOpt
 	println!("INSERT {}",htl_update.len());
 	Ok(htl_update)
 }
+async fn read_notifications(client:&Client,local_instance:&str,token:String,until_id:Option<String>)->Result<Vec<RawNotification>,String>{
+	let req_builder=client.post(format!("{}/api/i/notifications",local_instance));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	#[derive(Serialize,Deserialize,Debug)]
+	struct NotificationsRequestJson{
+		#[serde(skip_serializing_if = "Option::is_none")]
+		#[serde(rename = "untilId")]
+		until_id:Option<String>,
+		limit:u8,
+		i:String,
+	}
+	let req_body=NotificationsRequestJson{
+		until_id,
+		limit:20,
+		i:token,
+	};
+	let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+	let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+	let req_builder=req_builder.body(req_body);
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
+	let res=req_builder.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()))
+	}
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&res).map_err(|e|e.to_string())
+}
+const THREAD_MAX_DEPTH:usize=4;
+const THREAD_LIMIT:u8=30;
+const SEARCH_LIMIT:u8=20;
+async fn read_user_detail(client:&Client,local_instance:&str,token:String,user_id:String)->Result<RawUserDetail,String>{
+	let req_builder=client.post(format!("{}/api/users/show",local_instance));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	#[derive(Serialize,Deserialize,Debug)]
+	struct UserShowRequestJson{
+		#[serde(rename = "userId")]
+		user_id:String,
+		i:String,
+	}
+	let req_body=UserShowRequestJson{
+		user_id,
+		i:token,
+	};
+	let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+	let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+	let req_builder=req_builder.body(req_body);
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
+	let res=req_builder.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()))
+	}
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&res).map_err(|e|e.to_string())
+}
+const REACTIONS_LIMIT:u8=20;
+async fn read_reactions(client:&Client,local_instance:&str,token:String,note_id:&str,reaction:&str,until_id:Option<String>)->Result<Vec<RawReaction>,String>{
+	let req_builder=client.post(format!("{}/api/notes/reactions",local_instance));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	#[derive(Serialize,Deserialize,Debug)]
+	struct ReactionsRequestJson<'a>{
+		#[serde(rename = "noteId")]
+		note_id:&'a str,
+		#[serde(rename = "type")]
+		reaction:&'a str,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		#[serde(rename = "untilId")]
+		until_id:Option<String>,
+		limit:u8,
+		i:String,
+	}
+	let req_body=ReactionsRequestJson{
+		note_id,
+		reaction,
+		until_id,
+		limit:REACTIONS_LIMIT,
+		i:token,
+	};
+	let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+	let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+	let req_builder=req_builder.body(req_body);
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
+	let res=req_builder.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()))
+	}
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&res).map_err(|e|e.to_string())
+}
+async fn read_search(client:&Client,local_instance:&str,token:String,opt:SearchOption)->Result<Vec<RawNote>,String>{
+	let req_builder=client.post(format!("{}/api/notes/search",local_instance));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	#[derive(Serialize,Deserialize,Debug)]
+	struct SearchRequestJson{
+		query:String,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		#[serde(rename = "userId")]
+		user_id:Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		host:Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		#[serde(rename = "untilId")]
+		until_id:Option<String>,
+		limit:u8,
+		i:String,
+	}
+	let req_body=SearchRequestJson{
+		query:opt.query,
+		user_id:opt.user_id,
+		host:opt.host,
+		until_id:opt.until_id,
+		limit:SEARCH_LIMIT,
+		i:token,
+	};
+	let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+	let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+	let req_builder=req_builder.body(req_body);
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(10));
+	let res=req_builder.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()))
+	}
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&res).map_err(|e|e.to_string())
+}
+async fn read_thread_api<T:serde::de::DeserializeOwned>(client:&Client,local_instance:&str,token:&str,endpoint:&str,note_id:&str,limit:Option<u8>)->Result<T,String>{
+	let req_builder=client.post(format!("{}/api/notes/{}",local_instance,endpoint));
+	let req_builder=req_builder.header(reqwest::header::CONTENT_TYPE,"application/json");
+	#[derive(Serialize,Deserialize,Debug)]
+	struct ThreadRequestJson<'a>{
+		#[serde(rename = "noteId")]
+		note_id:&'a str,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		limit:Option<u8>,
+		i:&'a str,
+	}
+	let req_body=ThreadRequestJson{
+		note_id,
+		limit,
+		i:token,
+	};
+	let req_body=serde_json::to_string(&req_body).map_err(|e|e.to_string())?;
+	let req_builder=req_builder.header(reqwest::header::CONTENT_LENGTH,req_body.len());
+	let req_builder=req_builder.body(req_body);
+	let req_builder=req_builder.timeout(std::time::Duration::from_secs(5));
+	let res=req_builder.send().await.map_err(|e|e.to_string())?;
+	if res.status()!=200{
+		return Err(format!("post status {}",res.status()))
+	}
+	let res=res.bytes().await.map_err(|e|e.to_string())?;
+	serde_json::from_slice(&res).map_err(|e|e.to_string())
+}
+//深さ優先で返信を並べる
+fn read_thread_children<'a>(client:&'a Client,local_instance:&'a str,token:&'a str,note_id:String,depth:usize,children:&'a mut Vec<(usize,RawNote)>)->futures::future::BoxFuture<'a,Result<(),String>>{
+	Box::pin(async move{
+		let mut notes:Vec<RawNote>=read_thread_api(client,local_instance,token,"children",&note_id,Some(THREAD_LIMIT)).await?;
+		notes.sort_by(|a,b|a.created_at.cmp(&b.created_at));
+		for note in notes{
+			let id=note.id.clone();
+			let has_replies=note.replies_count>0;
+			children.push((depth,note));
+			if has_replies&&depth<THREAD_MAX_DEPTH{
+				read_thread_children(client,local_instance,token,id,depth+1,children).await?;
+			}
+		}
+		Ok(())
+	})
+}
+async fn read_thread(client:&Client,local_instance:&str,token:String,note_id:String)->Result<RawThread,String>{
+	let note=read_thread_api(client,local_instance,&token,"show",&note_id,None).await?;
+	//直近の親から順に返ってくる
+	let mut ancestors:Vec<RawNote>=read_thread_api(client,local_instance,&token,"conversation",&note_id,Some(THREAD_LIMIT)).await?;
+	ancestors.reverse();
+	let mut children=vec![];
+	read_thread_children(client,local_instance,&token,note_id.clone(),1,&mut children).await?;
+	Ok(RawThread{
+		id:note_id,
+		note,
+		ancestors,
+		children,
+	})
+}
 fn reactions_hash(note:&RawNote)->u64{
 	let mut hasher = std::collections::hash_map::DefaultHasher::new();
 	let mut hash=0;
@@ -784,6 +2198,12 @@ fn reactions_hash(note:&RawNote)->u64{
 		id.hash(&mut hasher);
 		hash+=*r;
 	}
+	note.my_reaction.hash(&mut hasher);
+	//アンケートの票が変わった時も読み直す
+	for c in note.poll.iter().flat_map(|p|p.choices.iter()){
+		c.is_voted.hash(&mut hasher);
+		hash+=c.votes;
+	}
 	hash+=hasher.finish();
 	hash
 }
@@ -803,9 +2223,14 @@ pub struct RawNote{
 	#[serde(rename = "reactionEmojis")]
 	pub reaction_emojis:HashMap<String,String>,
 	pub reactions:HashMap<String,u64>,
+	#[serde(rename = "myReaction")]
+	pub my_reaction:Option<String>,
 	#[serde(rename = "renoteCount")]
 	renote_count:u64,
 	renote:Option<Box<RawNote>>,
+	#[serde(rename = "replyId")]
+	reply_id:Option<String>,
+	reply:Option<Box<RawNote>>,
 	#[serde(rename = "repliesCount")]
 	replies_count:u64,
 	#[serde(rename = "uri")]
@@ -813,6 +2238,35 @@ pub struct RawNote{
 	user:RawUser,
 	visibility:String,
 	user_id:Option<String>,
+	#[serde(default)]
+	poll:Option<RawPoll>,
+}
+#[derive(Serialize,Deserialize,Debug)]
+struct RawPoll{
+	choices:Vec<RawPollChoice>,
+	multiple:bool,
+	#[serde(rename = "expiresAt")]
+	expires_at:Option<String>,
+}
+#[derive(Serialize,Deserialize,Debug)]
+struct RawPollChoice{
+	text:String,
+	votes:u64,
+	#[serde(rename = "isVoted")]
+	is_voted:bool,
+}
+impl From<RawPoll> for data_model::Poll{
+	fn from(poll:RawPoll)->Self{
+		Self{
+			choices:poll.choices.into_iter().map(|c|data_model::PollChoice{
+				text:c.text,
+				votes:c.votes,
+				is_voted:c.is_voted,
+			}).collect(),
+			multiple:poll.multiple,
+			expires_at:poll.expires_at.and_then(|t|chrono::DateTime::parse_from_rfc3339(&t).ok()).map(|t|t.to_utc()),
+		}
+	}
 }
 impl RawNote{
 	fn created_at(&self)->chrono::DateTime<chrono::Utc>{
@@ -820,6 +2274,17 @@ impl RawNote{
 	}
 }
 #[derive(Serialize,Deserialize,Debug)]
+pub struct RawNotification{
+	id:String,
+	#[serde(rename = "createdAt")]
+	created_at:String,
+	#[serde(rename = "type")]
+	t:String,
+	user:Option<RawUser>,
+	note:Option<RawNote>,
+	reaction:Option<String>,
+}
+#[derive(Serialize,Deserialize,Debug)]
 pub struct RawFile{
 	pub id:String,//9t8xrxeg8f
 	pub blurhash:Option<String>,//eHRomc?]RP%2pHoZVZtRtRVtWTV@RPayV@-;tRWAtRoz.SjboykBV@
@@ -0,0 +1,571 @@
+//MFM(Misskey Flavored Markdown)の構文木
+#[derive(Clone,Debug,PartialEq)]
+pub enum MfmNode{
+	Text(String),
+	EmojiCode(String),
+	Url(String),
+	Link{
+		silent:bool,
+		url:String,
+		label:Vec<MfmNode>,
+	},
+	Mention{
+		username:String,
+		host:Option<String>,
+		acct:String,
+	},
+	Hashtag(String),
+	InlineCode(String),
+	CodeBlock{
+		lang:Option<String>,
+		code:String,
+	},
+	Bold(Vec<MfmNode>),
+	Italic(Vec<MfmNode>),
+	Strike(Vec<MfmNode>),
+	Small(Vec<MfmNode>),
+	Center(Vec<MfmNode>),
+	Quote(Vec<MfmNode>),
+	Search(String),
+	Fn{
+		name:String,
+		args:Vec<(String,Option<String>)>,
+		children:Vec<MfmNode>,
+	},
+}
+//入れ子の上限を超えた部分はテキストとして扱う
+const MAX_DEPTH:usize=20;
+const SEARCH_KEYWORDS:[&'static str;4]=["検索","search","[検索]","[search]"];
+const HASHTAG_STOP_CHARS:&'static str=" \t\r\n\u{3000}.,!?'\"#:/[]【】()「」（）<>";
+pub fn parse(src:&str)->Vec<MfmNode>{
+	parse_nodes(src,0,true)
+}
+//ユーザー名などに使う絵文字のみの構文
+pub fn parse_simple(src:&str)->Vec<MfmNode>{
+	let mut out=Output::default();
+	let mut i=0;
+	while i<src.len(){
+		if let Some((node,len))=emoji_code(&src[i..]){
+			out.push(node);
+			i+=len;
+			continue;
+		}
+		let c=src[i..].chars().next().unwrap();
+		out.text.push(c);
+		i+=c.len_utf8();
+	}
+	out.finish()
+}
+#[derive(Default)]
+struct Output{
+	nodes:Vec<MfmNode>,
+	text:String,
+}
+impl Output{
+	fn flush(&mut self){
+		if !self.text.is_empty(){
+			self.nodes.push(MfmNode::Text(std::mem::take(&mut self.text)));
+		}
+	}
+	fn push(&mut self,node:MfmNode){
+		self.flush();
+		self.nodes.push(node);
+	}
+	fn push_block(&mut self,node:MfmNode){
+		//ブロック要素の直前の改行は要素の区切りとして消費する
+		if self.text.ends_with('\n'){
+			self.text.pop();
+		}
+		self.push(node);
+	}
+	fn finish(mut self)->Vec<MfmNode>{
+		self.flush();
+		self.nodes
+	}
+}
+fn parse_nodes(src:&str,depth:usize,allow_block:bool)->Vec<MfmNode>{
+	if depth>MAX_DEPTH{
+		return vec![MfmNode::Text(src.to_owned())];
+	}
+	let mut out=Output::default();
+	let mut i=0;
+	let mut line_start=true;
+	while i<src.len(){
+		if allow_block&&line_start{
+			if let Some((node,len))=block(&src[i..],depth){
+				out.push_block(node);
+				i+=len;
+				continue;
+			}
+		}
+		if let Some((node,len))=inline(src,i,depth){
+			out.push(node);
+			i+=len;
+			line_start=false;
+			continue;
+		}
+		let c=src[i..].chars().next().unwrap();
+		out.text.push(c);
+		i+=c.len_utf8();
+		line_start=c=='\n';
+	}
+	out.finish()
+}
+fn line_end(s:&str)->usize{
+	s.find('\n').unwrap_or(s.len())
+}
+//行末の改行も含めた長さ
+fn with_newline(s:&str,end:usize)->usize{
+	if s[end..].starts_with('\n'){
+		end+1
+	}else{
+		end
+	}
+}
+fn block(rest:&str,depth:usize)->Option<(MfmNode,usize)>{
+	if rest.starts_with("```"){
+		let eol=rest.find('\n')?;
+		let lang=rest[3..eol].trim();
+		let body=&rest[eol+1..];
+		let close=if body.starts_with("```"){
+			0
+		}else{
+			body.find("\n```")?+1
+		};
+		let code=body[..close].trim_end_matches('\n').to_owned();
+		let end=eol+1+close+3;
+		return Some((MfmNode::CodeBlock{
+			lang:if lang.is_empty(){
+				None
+			}else{
+				Some(lang.to_owned())
+			},
+			code,
+		},with_newline(rest,end)));
+	}
+	if rest.starts_with('>'){
+		let mut lines=vec![];
+		let mut offset=0;
+		while rest[offset..].starts_with('>'){
+			let eol=offset+line_end(&rest[offset..]);
+			let line=&rest[offset+1..eol];
+			lines.push(line.strip_prefix(' ').unwrap_or(line));
+			offset=with_newline(rest,eol);
+		}
+		let content=lines.join("\n");
+		return Some((MfmNode::Quote(parse_nodes(&content,depth+1,true)),offset));
+	}
+	if rest.starts_with("<center>"){
+		let close=rest.find("</center>")?;
+		let inner=rest[8..close].trim_matches('\n');
+		let end=close+9;
+		return Some((MfmNode::Center(parse_nodes(inner,depth+1,false)),with_newline(rest,end)));
+	}
+	let eol=line_end(rest);
+	let line=&rest[..eol];
+	for keyword in SEARCH_KEYWORDS{
+		if line.len()<=keyword.len()+1{
+			continue;
+		}
+		let split=line.len()-keyword.len();
+		if !line.is_char_boundary(split)||!line[split..].eq_ignore_ascii_case(keyword){
+			continue;
+		}
+		let query=&line[..split];
+		if !query.ends_with(' ')&&!query.ends_with('\u{3000}'){
+			continue;
+		}
+		let query=query.trim();
+		if query.is_empty(){
+			continue;
+		}
+		return Some((MfmNode::Search(query.to_owned()),with_newline(rest,eol)));
+	}
+	None
+}
+fn is_word_char(c:Option<char>)->bool{
+	c.map(|c|c.is_ascii_alphanumeric()||c=='_').unwrap_or(false)
+}
+fn inline(src:&str,i:usize,depth:usize)->Option<(MfmNode,usize)>{
+	let rest=&src[i..];
+	let prev=src[..i].chars().next_back();
+	let first=rest.as_bytes()[0];
+	match first{
+		b'<'=>{
+			if rest.starts_with("<plain>"){
+				let close=rest.find("</plain>")?;
+				return Some((MfmNode::Text(rest[7..close].to_owned()),close+8));
+			}
+			for (open,close) in [("<b>","</b>"),("<i>","</i>"),("<s>","</s>"),("<small>","</small>")]{
+				if !rest.starts_with(open){
+					continue;
+				}
+				let end=rest[open.len()..].find(close)?+open.len();
+				let inner=&rest[open.len()..end];
+				if inner.is_empty(){
+					return None;
+				}
+				let children=parse_nodes(inner,depth+1,false);
+				let node=match open{
+					"<b>"=>MfmNode::Bold(children),
+					"<i>"=>MfmNode::Italic(children),
+					"<s>"=>MfmNode::Strike(children),
+					_=>MfmNode::Small(children),
+				};
+				return Some((node,end+close.len()));
+			}
+			if rest.starts_with("<http://")||rest.starts_with("<https://"){
+				let close=rest.find('>')?;
+				let url=&rest[1..close];
+				if url.contains(char::is_whitespace){
+					return None;
+				}
+				return Some((MfmNode::Url(url.to_owned()),close+1));
+			}
+			None
+		},
+		b'*'|b'_'=>{
+			let mark=&rest[..1];
+			let double=format!("{}{}",mark,mark);
+			if rest.starts_with(&double){
+				let end=rest[2..].find(&double)?+2;
+				let inner=&rest[2..end];
+				if inner.is_empty(){
+					return None;
+				}
+				if mark=="_"&&(is_word_char(prev)||!is_alnum_text(inner)){
+					return None;
+				}
+				return Some((MfmNode::Bold(parse_nodes(inner,depth+1,false)),end+2));
+			}
+			//*italic* と _italic_ は英数字のみ
+			if is_word_char(prev){
+				return None;
+			}
+			let end=rest[1..].find(mark)?+1;
+			let inner=&rest[1..end];
+			if inner.is_empty()||!is_alnum_text(inner){
+				return None;
+			}
+			Some((MfmNode::Italic(vec![MfmNode::Text(inner.to_owned())]),end+1))
+		},
+		b'~'=>{
+			if !rest.starts_with("~~"){
+				return None;
+			}
+			let end=rest[2..].find("~~")?+2;
+			let inner=&rest[2..end];
+			if inner.is_empty()||inner.contains('\n'){
+				return None;
+			}
+			Some((MfmNode::Strike(parse_nodes(inner,depth+1,false)),end+2))
+		},
+		b'`'=>{
+			if rest.starts_with("``"){
+				return None;
+			}
+			let end=rest[1..].find('`')?+1;
+			let inner=&rest[1..end];
+			if inner.is_empty()||inner.contains('\n'){
+				return None;
+			}
+			Some((MfmNode::InlineCode(inner.to_owned()),end+1))
+		},
+		b'$'=>function(rest,depth),
+		b'?'=>{
+			if rest.starts_with("?["){
+				link(&rest[1..],depth,true).map(|(node,len)|(node,len+1))
+			}else{
+				None
+			}
+		},
+		b'['=>link(rest,depth,false),
+		b'h'=>{
+			if is_word_char(prev){
+				return None;
+			}
+			url(rest).map(|url|(MfmNode::Url(url.to_owned()),url.len()))
+		},
+		b'@'=>{
+			if is_word_char(prev){
+				return None;
+			}
+			mention(rest)
+		},
+		b'#'=>{
+			if is_word_char(prev){
+				return None;
+			}
+			let end=rest[1..].find(|c:char|HASHTAG_STOP_CHARS.contains(c)).map(|v|v+1).unwrap_or(rest.len());
+			let tag=&rest[1..end];
+			if tag.is_empty()||tag.chars().all(|c|c.is_ascii_digit()){
+				return None;
+			}
+			Some((MfmNode::Hashtag(tag.to_owned()),end))
+		},
+		b':'=>emoji_code(rest),
+		_=>None,
+	}
+}
+fn is_alnum_text(s:&str)->bool{
+	s.chars().all(|c|c.is_ascii_alphanumeric()||c==' ')
+}
+fn emoji_code(rest:&str)->Option<(MfmNode,usize)>{
+	if !rest.starts_with(':'){
+		return None;
+	}
+	let end=rest[1..].find(|c:char|!(c.is_ascii_alphanumeric()||c=='_'||c=='+'||c=='-'))?+1;
+	if end==1||!rest[end..].starts_with(':'){
+		return None;
+	}
+	Some((MfmNode::EmojiCode(rest[1..end].to_owned()),end+1))
+}
+fn url(rest:&str)->Option<&str>{
+	if !rest.starts_with("http://")&&!rest.starts_with("https://"){
+		return None;
+	}
+	let end=rest.find(|c:char|!(c.is_ascii_alphanumeric()||"_./?&=%#-+!:,@~;".contains(c))).unwrap_or(rest.len());
+	//末尾の句読点はURLに含めない
+	let url=rest[..end].trim_end_matches(|c|c=='.'||c==',');
+	if url.ends_with("://"){
+		return None;
+	}
+	Some(url)
+}
+fn mention(rest:&str)->Option<(MfmNode,usize)>{
+	let is_user_char=|c:char|c.is_ascii_alphanumeric()||c=='_'||c=='-';
+	let end=rest[1..].find(|c:char|!is_user_char(c)).map(|v|v+1).unwrap_or(rest.len());
+	let username=rest[1..end].trim_end_matches('-');
+	if username.is_empty()||username.starts_with('-'){
+		return None;
+	}
+	let mut len=1+username.len();
+	let mut host=None;
+	if len==end&&rest[len..].starts_with('@'){
+		let host_start=len+1;
+		let host_end=rest[host_start..].find(|c:char|!(is_user_char(c)||c=='.')).map(|v|v+host_start).unwrap_or(rest.len());
+		let h=rest[host_start..host_end].trim_end_matches(|c|c=='.'||c=='-');
+		if !h.is_empty(){
+			host=Some(h.to_owned());
+			len=host_start+h.len();
+		}
+	}
+	Some((MfmNode::Mention{
+		username:username.to_owned(),
+		host,
+		acct:rest[..len].to_owned(),
+	},len))
+}
+fn function(rest:&str,depth:usize)->Option<(MfmNode,usize)>{
+	if !rest.starts_with("$["){
+		return None;
+	}
+	let name_end=rest[2..].find(|c:char|!(c.is_ascii_alphanumeric()||c=='_'))?+2;
+	let name=&rest[2..name_end];
+	if name.is_empty(){
+		return None;
+	}
+	let mut offset=name_end;
+	let mut args=vec![];
+	if rest[offset..].starts_with('.'){
+		let args_end=rest[offset..].find(|c:char|c.is_whitespace()||c==']')?+offset;
+		for arg in rest[offset+1..args_end].split(','){
+			if arg.is_empty(){
+				continue;
+			}
+			match arg.split_once('='){
+				Some((k,v))=>args.push((k.to_owned(),Some(v.to_owned()))),
+				None=>args.push((arg.to_owned(),None)),
+			}
+		}
+		offset=args_end;
+	}
+	let c=rest[offset..].chars().next()?;
+	if !c.is_whitespace(){
+		return None;
+	}
+	offset+=c.len_utf8();
+	//対応する閉じ括弧を探す
+	let mut level=0;
+	let mut close=None;
+	for (idx,c) in rest[offset..].char_indices(){
+		match c{
+			'['=>level+=1,
+			']'=>{
+				if level==0{
+					close=Some(idx+offset);
+					break;
+				}
+				level-=1;
+			},
+			_=>{}
+		}
+	}
+	let close=close?;
+	Some((MfmNode::Fn{
+		name:name.to_owned(),
+		args,
+		children:parse_nodes(&rest[offset..close],depth+1,false),
+	},close+1))
+}
+fn link(rest:&str,depth:usize,silent:bool)->Option<(MfmNode,usize)>{
+	let label_end=rest.find(']')?;
+	let label=&rest[1..label_end];
+	if label.is_empty()||label.contains('\n'){
+		return None;
+	}
+	if !rest[label_end+1..].starts_with('('){
+		return None;
+	}
+	let url_start=label_end+2;
+	let url_end=rest[url_start..].find(')')?+url_start;
+	let url=&rest[url_start..url_end];
+	if self::url(url).map(|u|u.len())!=Some(url.len()){
+		return None;
+	}
+	Some((MfmNode::Link{
+		silent,
+		url:url.to_owned(),
+		label:parse_nodes(label,depth+1,false),
+	},url_end+1))
+}
+#[cfg(test)]
+mod tests{
+	use super::*;
+
+	fn text(s:&str)->MfmNode{
+		MfmNode::Text(s.to_owned())
+	}
+	#[test]
+	fn nested_function(){
+		assert_eq!(parse("$[x2 $[fg.color=f00 赤]と$[flip.h,v 逆]]"),vec![
+			MfmNode::Fn{
+				name:"x2".to_owned(),
+				args:vec![],
+				children:vec![
+					MfmNode::Fn{
+						name:"fg".to_owned(),
+						args:vec![("color".to_owned(),Some("f00".to_owned()))],
+						children:vec![text("赤")],
+					},
+					text("と"),
+					MfmNode::Fn{
+						name:"flip".to_owned(),
+						args:vec![("h".to_owned(),None),("v".to_owned(),None)],
+						children:vec![text("逆")],
+					},
+				],
+			},
+		]);
+	}
+	#[test]
+	fn function_with_brackets_inside(){
+		assert_eq!(parse("$[bg.color=00f [a]]!"),vec![
+			MfmNode::Fn{
+				name:"bg".to_owned(),
+				args:vec![("color".to_owned(),Some("00f".to_owned()))],
+				children:vec![text("[a]")],
+			},
+			text("!"),
+		]);
+	}
+	#[test]
+	fn url_terminated_by_bracket(){
+		assert_eq!(parse("(https://example.com/a?b=c)"),vec![
+			text("("),
+			MfmNode::Url("https://example.com/a?b=c".to_owned()),
+			text(")"),
+		]);
+		assert_eq!(parse("[https://example.com]"),vec![
+			text("["),
+			MfmNode::Url("https://example.com".to_owned()),
+			text("]"),
+		]);
+		assert_eq!(parse("$[x2 https://example.com]"),vec![
+			MfmNode::Fn{
+				name:"x2".to_owned(),
+				args:vec![],
+				children:vec![MfmNode::Url("https://example.com".to_owned())],
+			},
+		]);
+	}
+	#[test]
+	fn url_trailing_punctuation(){
+		assert_eq!(parse("see https://example.com/a."),vec![
+			text("see "),
+			MfmNode::Url("https://example.com/a".to_owned()),
+			text("."),
+		]);
+	}
+	#[test]
+	fn link(){
+		assert_eq!(parse("?[label](https://example.com/)"),vec![
+			MfmNode::Link{
+				silent:true,
+				url:"https://example.com/".to_owned(),
+				label:vec![text("label")],
+			},
+		]);
+	}
+	#[test]
+	fn mention_with_host(){
+		assert_eq!(parse("@yojo@misskey.example.com."),vec![
+			MfmNode::Mention{
+				username:"yojo".to_owned(),
+				host:Some("misskey.example.com".to_owned()),
+				acct:"@yojo@misskey.example.com".to_owned(),
+			},
+			text("."),
+		]);
+		assert_eq!(parse("@local_user さん"),vec![
+			MfmNode::Mention{
+				username:"local_user".to_owned(),
+				host:None,
+				acct:"@local_user".to_owned(),
+			},
+			text(" さん"),
+		]);
+		//メールアドレスはメンションにしない
+		assert_eq!(parse("a@example.com"),vec![text("a@example.com")]);
+	}
+	#[test]
+	fn hashtag(){
+		assert_eq!(parse("#yojo_art #123 #タグ."),vec![
+			MfmNode::Hashtag("yojo_art".to_owned()),
+			text(" #123 "),
+			MfmNode::Hashtag("タグ".to_owned()),
+			text("."),
+		]);
+		assert_eq!(parse("a#b"),vec![text("a#b")]);
+	}
+	#[test]
+	fn unclosed_markup(){
+		assert_eq!(parse("**bold"),vec![text("**bold")]);
+		assert_eq!(parse("<b>bold"),vec![text("<b>bold")]);
+		assert_eq!(parse("$[x2 big"),vec![text("$[x2 big")]);
+		assert_eq!(parse("`code"),vec![text("`code")]);
+		assert_eq!(parse("[label](https://example.com"),vec![
+			text("[label]("),
+			MfmNode::Url("https://example.com".to_owned()),
+		]);
+	}
+	#[test]
+	fn search_block(){
+		assert_eq!(parse("yojo art 検索\nnext"),vec![
+			MfmNode::Search("yojo art".to_owned()),
+			text("next"),
+		]);
+	}
+	#[test]
+	fn depth_limit(){
+		let src="$[x2 ".repeat(MAX_DEPTH+2)+&"]".repeat(MAX_DEPTH+2);
+		let mut nodes=parse(&src);
+		for _ in 0..=MAX_DEPTH{
+			nodes=match nodes.pop(){
+				Some(MfmNode::Fn{children,..})=>children,
+				n=>panic!("{:?}",n),
+			};
+		}
+		assert!(matches!(nodes.as_slice(),[MfmNode::Text(_)]));
+	}
+}
@@ -0,0 +1,247 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{Account, ConfigFile};
+
+const APP_NAME:&str="YojoArtApp";
+//アプリで使うAPIの権限
+const PERMISSIONS:&[&str]=&[
+	"read:account",
+	"write:notes",
+	"read:reactions",
+	"write:reactions",
+	"read:notifications",
+	"read:following",
+	"write:following",
+	"write:mutes",
+	"write:blocks",
+	"read:drive",
+	"write:drive",
+];
+const POLL_INTERVAL:u64=3*1000;
+const LOGIN_TIMEOUT:u64=10*60*1000;
+const CALLBACK_HTML:&str="<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><body>ログインしました。アプリに戻ってください。</body></html>";
+pub struct MiAuthSession{
+	instance:String,
+	session:String,
+}
+#[derive(Deserialize,Debug)]
+struct CheckResult{
+	ok:bool,
+	token:Option<String>,
+	user:Option<CheckUser>,
+}
+#[derive(Deserialize,Debug)]
+struct CheckUser{
+	id:String,
+	username:String,
+	#[serde(rename = "avatarUrl")]
+	avatar_url:Option<String>,
+}
+//https://を補い末尾の/を取り除く
+pub fn normalize_instance(input:&str)->Option<String>{
+	let input=input.trim().trim_end_matches('/');
+	if input.is_empty(){
+		return None;
+	}
+	let input=if input.starts_with("http://")||input.starts_with("https://"){
+		input.to_owned()
+	}else{
+		format!("https://{}",input)
+	};
+	let url=reqwest::Url::parse(&input).ok()?;
+	url.host_str()?;
+	Some(input)
+}
+impl MiAuthSession{
+	pub fn new(instance:String)->Self{
+		Self{
+			instance,
+			session:uuid::Uuid::new_v4().to_string(),
+		}
+	}
+	pub fn url(&self,callback:Option<&str>)->String{
+		let callback=callback.map(|c|format!("&callback={}",urlencoding::encode(c))).unwrap_or_default();
+		format!("{}/miauth/{}?name={}&permission={}{}",self.instance,self.session,APP_NAME,PERMISSIONS.join(","),callback)
+	}
+	//許可されていればアカウントを返す
+	pub async fn check(&self,client:&Client)->Result<Option<Account>,String>{
+		let build=client.post(format!("{}/api/miauth/{}/check",self.instance,self.session));
+		let build=build.header(reqwest::header::CONTENT_TYPE,"application/json");
+		let build=build.body("{}");
+		let res=build.send().await.map_err(|e|e.to_string())?;
+		if !res.status().is_success(){
+			return Err(format!("miauth check status {}",res.status()));
+		}
+		let res=res.bytes().await.map_err(|e|e.to_string())?;
+		let res:CheckResult=serde_json::from_slice(&res).map_err(|e|e.to_string())?;
+		let token=match res.token{
+			Some(token) if res.ok=>token,
+			_=>return Ok(None),
+		};
+		Ok(Some(Account{
+			instance:self.instance.clone(),
+			token,
+			user_id:res.user.as_ref().map(|u|u.id.clone()),
+			username:res.user.as_ref().map(|u|u.username.clone()),
+			avatar_url:res.user.and_then(|u|u.avatar_url),
+		}))
+	}
+	//ループバックへのコールバックを待ちつつ定期的に確認する
+	pub async fn wait_token(&self,client:&Client,listener:Option<tokio::net::TcpListener>,cancel:Arc<AtomicBool>)->Result<Account,String>{
+		let start=std::time::Instant::now();
+		while start.elapsed().as_millis()<LOGIN_TIMEOUT as u128{
+			if cancel.load(std::sync::atomic::Ordering::Relaxed){
+				return Err("cancel".to_owned());
+			}
+			tokio::select!{
+				_=accept_callback(listener.as_ref())=>{},
+				_=tokio::time::sleep(tokio::time::Duration::from_millis(POLL_INTERVAL))=>{},
+			}
+			match self.check(client).await{
+				Ok(Some(account))=>return Ok(account),
+				Ok(None)=>{},
+				Err(e)=>eprintln!("{}",e),
+			}
+		}
+		Err("timeout".to_owned())
+	}
+}
+async fn accept_callback(listener:Option<&tokio::net::TcpListener>)->Option<()>{
+	let listener=match listener{
+		Some(listener)=>listener,
+		None=>return std::future::pending().await,
+	};
+	let (mut stream,_)=listener.accept().await.ok()?;
+	let mut buf=[0u8;1024];
+	let _=stream.read(&mut buf).await;
+	let res=format!("HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",CALLBACK_HTML.len(),CALLBACK_HTML);
+	let _=stream.write_all(res.as_bytes()).await;
+	let _=stream.shutdown().await;
+	Some(())
+}
+//ログインが済むまで待つ
+pub(crate) async fn wait_login(recv:&mut tokio::sync::watch::Receiver<Arc<ConfigFile>>)->Option<Arc<ConfigFile>>{
+	loop{
+		let config=recv.borrow_and_update().clone();
+		if config.token.is_some()&&config.instance.is_some(){
+			return Some(config);
+		}
+		recv.changed().await.ok()?;
+	}
+}
+#[cfg(test)]
+mod tests{
+	use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc};
+
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	use super::*;
+
+	//checkを受けるたびに数を数え、ok_afterを超えたらトークンを返すインスタンス
+	async fn fake_instance(session:String,ok_after:usize)->(String,Arc<AtomicUsize>){
+		let listener=tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let instance=format!("http://{}",listener.local_addr().unwrap());
+		let checks=Arc::new(AtomicUsize::new(0));
+		let checks0=checks.clone();
+		tokio::spawn(async move{
+			loop{
+				let (mut stream,_)=match listener.accept().await{
+					Ok(v)=>v,
+					Err(_)=>break,
+				};
+				let mut buf=vec![];
+				let mut chunk=[0u8;1024];
+				while !buf.windows(4).any(|w|w==b"\r\n\r\n"){
+					match stream.read(&mut chunk).await{
+						Ok(0)|Err(_)=>break,
+						Ok(n)=>buf.extend_from_slice(&chunk[..n]),
+					}
+				}
+				let req=String::from_utf8_lossy(&buf);
+				let path=format!("POST /api/miauth/{}/check ",session);
+				let (status,body)=if req.starts_with(&path){
+					let count=checks0.fetch_add(1,Ordering::SeqCst)+1;
+					if count>ok_after{
+						("200 OK",r#"{"ok":true,"token":"TOKEN","user":{"id":"9abc","username":"yojo","avatarUrl":null}}"#)
+					}else{
+						("200 OK",r#"{"ok":false}"#)
+					}
+				}else{
+					("404 Not Found","{}")
+				};
+				let res=format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",status,body.len(),body);
+				let _=stream.write_all(res.as_bytes()).await;
+				let _=stream.shutdown().await;
+			}
+		});
+		(instance,checks)
+	}
+	#[tokio::test]
+	async fn check_returns_account_once_authorized(){
+		let session=MiAuthSession::new(String::new());
+		let (instance,checks)=fake_instance(session.session.clone(),1).await;
+		let session=MiAuthSession{
+			instance:instance.clone(),
+			..session
+		};
+		let client=Client::new();
+		assert!(session.check(&client).await.unwrap().is_none());
+		let account=session.check(&client).await.unwrap().unwrap();
+		assert_eq!(account.instance,instance);
+		assert_eq!(account.token,"TOKEN");
+		assert_eq!(account.user_id.as_deref(),Some("9abc"));
+		assert_eq!(account.username.as_deref(),Some("yojo"));
+		assert_eq!(account.avatar_url,None);
+		assert_eq!(checks.load(Ordering::SeqCst),2);
+	}
+	#[tokio::test]
+	async fn check_reports_http_error(){
+		let session=MiAuthSession::new(String::new());
+		//別のセッションIDにだけ応答するので404になる
+		let (instance,_)=fake_instance("other".to_owned(),0).await;
+		let session=MiAuthSession{
+			instance,
+			..session
+		};
+		assert!(session.check(&Client::new()).await.is_err());
+	}
+	#[tokio::test]
+	async fn wait_token_checks_on_loopback_callback(){
+		let session=MiAuthSession::new(String::new());
+		let (instance,checks)=fake_instance(session.session.clone(),0).await;
+		let session=MiAuthSession{
+			instance,
+			..session
+		};
+		let callback=tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let callback_addr=callback.local_addr().unwrap();
+		//ブラウザからのリダイレクトの代わり
+		let browser=tokio::spawn(async move{
+			tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+			let mut stream=tokio::net::TcpStream::connect(callback_addr).await.unwrap();
+			stream.write_all(b"GET /?session=x HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+			let mut res=String::new();
+			stream.read_to_string(&mut res).await.unwrap();
+			res
+		});
+		let start=std::time::Instant::now();
+		let account=session.wait_token(&Client::new(),Some(callback),Arc::new(AtomicBool::new(false))).await.unwrap();
+		//ポーリングの間隔を待たずにコールバックで確認している
+		assert!(start.elapsed().as_millis()<POLL_INTERVAL as u128);
+		assert_eq!(account.token,"TOKEN");
+		assert_eq!(checks.load(Ordering::SeqCst),1);
+		let res=browser.await.unwrap();
+		assert!(res.starts_with("HTTP/1.1 200 OK"));
+		assert!(res.ends_with(CALLBACK_HTML));
+	}
+	#[tokio::test]
+	async fn wait_token_cancel(){
+		let session=MiAuthSession::new("http://127.0.0.1:9".to_owned());
+		let res=session.wait_token(&Client::new(),None,Arc::new(AtomicBool::new(true))).await;
+		assert_eq!(res.unwrap_err(),"cancel");
+	}
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc}};
+
+use serde::de::DeserializeOwned;
+
+use crate::{data_model, load_misskey::{ApiMeta, RawNote, TimeLine}};
+
+//タイムライン毎に残すノートの数
+const STORE_NOTES:usize=100;
+//ストリーミングで届いたノートをまとめて書き込む間隔
+const FLUSH_DELAY:std::time::Duration=std::time::Duration::from_secs(2);
+//前回表示したノートをキャッシュディレクトリに残して起動直後やオフラインで使う
+pub(crate) struct NoteStore{
+	dir:PathBuf,
+	account:String,
+	//同じファイルへの読み書きが重ならないようにする
+	lock:tokio::sync::Mutex<()>,
+	//まだ書き込んでいない変更
+	pending:std::sync::Mutex<Vec<StoreOp>>,
+	flush_scheduled:AtomicBool,
+}
+enum StoreOp{
+	Notes(PathBuf,Vec<serde_json::Value>),
+	Deleted(String),
+	Reaction{
+		id:String,
+		reaction:String,
+		emoji:Option<(String,String)>,
+		mine:bool,
+		reacted:bool,
+	},
+}
+impl NoteStore{
+	pub(crate) fn new(account:Option<String>)->Self{
+		Self{
+			dir:std::path::Path::new(&data_model::cache_dir()).join("notes"),
+			account:account.unwrap_or_else(||"default".to_owned()),
+			lock:tokio::sync::Mutex::new(()),
+			pending:std::sync::Mutex::new(vec![]),
+			flush_scheduled:AtomicBool::new(false),
+		}
+	}
+	fn file(&self,name:&str)->PathBuf{
+		self.dir.join(format!("{}_{}.json",self.account,name))
+	}
+	fn timeline_file(&self,tl:&TimeLine)->PathBuf{
+		let tl=serde_json::to_string(tl).unwrap_or_default();
+		self.file(&uuid::Uuid::new_v3(&uuid::Uuid::NAMESPACE_URL,tl.as_bytes()).simple().to_string())
+	}
+	async fn read<T:DeserializeOwned>(path:&PathBuf)->Option<T>{
+		let buf=tokio::fs::read(path).await.ok()?;
+		serde_json::from_slice(&buf).map_err(|e|eprintln!("note store {:?} {:?}",path,e)).ok()
+	}
+	async fn write(&self,path:&PathBuf,value:&serde_json::Value){
+		if !tokio::fs::try_exists(&self.dir).await.unwrap_or(true){
+			println!("create_dir_all {:?}",tokio::fs::create_dir_all(&self.dir).await);
+		}
+		if let Err(e)=tokio::fs::write(path,value.to_string()).await{
+			eprintln!("note store {:?} {:?}",path,e);
+		}
+	}
+	//タイムラインのファイルを全て返す
+	async fn timeline_files(&self)->Vec<PathBuf>{
+		let mut files=vec![];
+		let mut dir=match tokio::fs::read_dir(&self.dir).await{
+			Ok(dir)=>dir,
+			Err(_)=>return files,
+		};
+		let prefix=format!("{}_",self.account);
+		while let Ok(Some(entry))=dir.next_entry().await{
+			let name=entry.file_name();
+			let name=name.to_string_lossy();
+			let is_timeline=name.strip_prefix(&prefix).and_then(|n|n.strip_suffix(".json")).map(|n|n.len()==32&&n.chars().all(|c|c.is_ascii_hexdigit())).unwrap_or(false);
+			if is_timeline{
+				files.push(entry.path());
+			}
+		}
+		files
+	}
+	//read_timelineと同じく古い順で返す
+	pub(crate) async fn notes(&self,tl:&TimeLine)->Vec<RawNote>{
+		self.flush().await;
+		let _lock=self.lock.lock().await;
+		let notes:Vec<RawNote>=Self::read(&self.timeline_file(tl)).await.unwrap_or_default();
+		notes.into_iter().rev().collect()
+	}
+	//新しいノートを同じIDの古い内容と置き換えて保存する
+	pub(crate) fn save_notes(self:&Arc<Self>,tl:&TimeLine,notes:&[RawNote]){
+		if notes.is_empty(){
+			return;
+		}
+		let notes=notes.iter().filter_map(|n|serde_json::to_value(n).ok()).collect();
+		self.push(StoreOp::Notes(self.timeline_file(tl),notes));
+	}
+	pub(crate) fn delete_note(self:&Arc<Self>,id:String){
+		self.push(StoreOp::Deleted(id));
+	}
+	pub(crate) fn update_reaction(self:&Arc<Self>,id:String,reaction:String,emoji:Option<(String,String)>,mine:bool,reacted:bool){
+		self.push(StoreOp::Reaction{
+			id,
+			reaction,
+			emoji,
+			mine,
+			reacted,
+		});
+	}
+	//受信処理を止めないよう少し待ってからまとめて書き込む
+	fn push(self:&Arc<Self>,op:StoreOp){
+		self.pending.lock().unwrap().push(op);
+		if self.flush_scheduled.swap(true,Ordering::AcqRel){
+			return;
+		}
+		let store=self.clone();
+		tokio::spawn(async move{
+			tokio::time::sleep(FLUSH_DELAY).await;
+			store.flush().await;
+		});
+	}
+	pub(crate) async fn flush(&self){
+		let _lock=self.lock.lock().await;
+		self.flush_scheduled.store(false,Ordering::Release);
+		let ops=std::mem::take(&mut *self.pending.lock().unwrap());
+		if ops.is_empty(){
+			return;
+		}
+		//読み込んだファイルの内容
+		let mut files:HashMap<PathBuf,Vec<serde_json::Value>>=HashMap::new();
+		let mut all_loaded=false;
+		for op in ops{
+			match op{
+				StoreOp::Notes(path,notes)=>{
+					if !files.contains_key(&path){
+						let stored=Self::read(&path).await.unwrap_or_default();
+						files.insert(path.clone(),stored);
+					}
+					let stored=files.get_mut(&path).unwrap();
+					*stored=merge_notes(std::mem::take(stored),notes);
+				},
+				op=>{
+					//どのタイムラインに入っているか分からないので全て書き換える
+					if !all_loaded{
+						for path in self.timeline_files().await{
+							if !files.contains_key(&path){
+								let stored=Self::read(&path).await.unwrap_or_default();
+								files.insert(path,stored);
+							}
+						}
+						all_loaded=true;
+					}
+					for stored in files.values_mut(){
+						apply(stored,&op);
+					}
+				},
+			}
+		}
+		for (path,notes) in files{
+			self.write(&path,&serde_json::Value::Array(notes)).await;
+		}
+	}
+	pub(crate) async fn meta(&self)->Option<(ApiMeta,HashMap<String,String>)>{
+		let _lock=self.lock.lock().await;
+		let mut stored:serde_json::Value=Self::read(&self.file("meta")).await?;
+		let meta=serde_json::from_value(stored.get_mut("meta")?.take()).ok()?;
+		let emojis=serde_json::from_value(stored.get_mut("emojis")?.take()).ok()?;
+		Some((meta,emojis))
+	}
+	pub(crate) async fn save_meta(&self,meta:&ApiMeta,emojis:&HashMap<String,String>){
+		let _lock=self.lock.lock().await;
+		self.write(&self.file("meta"),&serde_json::json!({
+			"meta":meta,
+			"emojis":emojis,
+		})).await;
+	}
+}
+fn merge_notes(stored:Vec<serde_json::Value>,notes:Vec<serde_json::Value>)->Vec<serde_json::Value>{
+	let mut merged=HashMap::new();
+	for note in stored.into_iter().chain(notes){
+		if let Some(id)=note.get("id").and_then(|id|id.as_str()).map(|id|id.to_owned()){
+			merged.insert(id,note);
+		}
+	}
+	let mut merged:Vec<_>=merged.into_iter().collect();
+	merged.sort_by(|(a,_),(b,_)|b.cmp(a));
+	merged.truncate(STORE_NOTES);
+	merged.into_iter().map(|(_,n)|n).collect()
+}
+fn note_id(note:&serde_json::Value)->Option<&str>{
+	note.get("id").and_then(|id|id.as_str())
+}
+//削除とリアクションの変更を保存済みのノートに反映する
+fn apply(stored:&mut Vec<serde_json::Value>,op:&StoreOp){
+	match op{
+		StoreOp::Deleted(id)=>{
+			//リノート元が消えたリノートも消す
+			stored.retain(|n|note_id(n)!=Some(id)&&n.get("renote").and_then(note_id)!=Some(id));
+		},
+		StoreOp::Reaction{id,reaction,emoji,mine,reacted}=>{
+			for n in stored.iter_mut(){
+				let note=if note_id(n)==Some(id){
+					n
+				}else{
+					match n.get_mut("renote").filter(|r|note_id(r)==Some(id)){
+						Some(renote)=>renote,
+						None=>continue,
+					}
+				};
+				if let Some(reactions)=note.get_mut("reactions").and_then(|r|r.as_object_mut()){
+					let count=reactions.get(reaction).and_then(|c|c.as_u64()).unwrap_or(0);
+					let count=if *reacted{
+						count+1
+					}else{
+						count.saturating_sub(1)
+					};
+					if count==0{
+						reactions.remove(reaction);
+					}else{
+						reactions.insert(reaction.clone(),count.into());
+					}
+				}
+				if let Some((name,url))=emoji{
+					if let Some(emojis)=note.get_mut("reactionEmojis").and_then(|r|r.as_object_mut()){
+						emojis.insert(name.clone(),url.clone().into());
+					}
+				}
+				if *mine{
+					note["myReaction"]=if *reacted{
+						reaction.clone().into()
+					}else{
+						serde_json::Value::Null
+					};
+				}
+			}
+		},
+		StoreOp::Notes(..)=>{},
+	}
+}